@@ -0,0 +1,268 @@
+use std::{fs, path::Path};
+
+use super::{Action, Binding, Config, ConfigError, Mode};
+
+/// Reads and compiles a binding file with shell bodies.
+pub fn load_dsl(path: &Path) -> Result<Config, ConfigError> {
+    let text = fs::read_to_string(path).map_err(|err| ConfigError::Io(path.into(), err))?;
+    parse_dsl(&text)
+}
+
+/// The headers are combos, the bodies are given to `sh -c` as they are,
+/// and the blocks are sub-modes, like the arms of `sequence!`:
+/// ```text
+/// # network
+/// super_l + c {
+///     shift + n: nm-connection-editor
+///     n: urxvt -e nmtui
+///     a { a: pavucontrol; j: pw-jack catia }
+/// }
+/// super_l + r: { bspc wm -r; notify-send reloaded; }
+/// ```
+/// A body ends at the end of the line, at `;` or at the `}` of its mode,
+/// so more commands are grouped with `{ ...; }` or `( ... )` like in shell.
+pub fn parse_dsl(text: &str) -> Result<Config, ConfigError> {
+    let mut config = Config::new();
+    let mut parser = Parser {
+        text,
+        position: 0,
+        line: 1,
+        line_start: 0,
+    };
+    config.root = parser.mode(&mut config, None)?;
+    Ok(config)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    /// byte offset in `text`
+    position: usize,
+    line: usize,
+    /// byte offset of the current line
+    line_start: usize,
+}
+
+/// line and column, from 1
+type Location = (usize, usize);
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn bump(&mut self) {
+        if let Some(c) = self.peek() {
+            self.position += c.len_utf8();
+            if c == '\n' {
+                self.line += 1;
+                self.line_start = self.position;
+            }
+        }
+    }
+
+    fn location(&self) -> Location {
+        (self.line, self.text[self.line_start..self.position].chars().count() + 1)
+    }
+
+    fn error(&self, (line, column): Location, details: impl std::fmt::Display) -> ConfigError {
+        ConfigError::Syntax(format!("line {}, column {}: {}", line, column, details))
+    }
+
+    /// whitespace, separators and comments between the bindings
+    fn skip_separators(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                '#' => {
+                    while !matches!(self.peek(), Some('\n') | None) {
+                        self.bump();
+                    }
+                }
+                ';' => self.bump(),
+                c if c.is_whitespace() => self.bump(),
+                _ => return,
+            }
+        }
+    }
+
+    /// The bindings until the end, or until the `}` of the block opened at `opening`.
+    fn mode(&mut self, config: &mut Config, opening: Option<Location>) -> Result<Mode, ConfigError> {
+        let mut bindings = vec![];
+        loop {
+            self.skip_separators();
+            match (self.peek(), opening) {
+                (None, Some(opening)) => return Err(self.error(opening, "the block is not closed with }")),
+                (None, None) => break,
+                (Some('}'), Some(_)) => {
+                    self.bump();
+                    break;
+                }
+                (Some('}'), None) => return Err(self.error(self.location(), "unexpected }")),
+                (Some(_), _) => bindings.push(self.binding(config)?),
+            }
+        }
+        Ok(Mode {
+            bindings,
+            locked: false,
+            timeout: None,
+        })
+    }
+
+    fn binding(&mut self, config: &mut Config) -> Result<Binding, ConfigError> {
+        let location = self.location();
+        let start = self.position;
+        while !matches!(self.peek(), Some(':' | '{' | '}' | ';' | '\n') | None) {
+            self.bump();
+        }
+        let keys = self.text[start..self.position].trim();
+        let combo = config.combo(keys).map_err(|err| self.error(location, err))?;
+        let action = match self.peek() {
+            Some(':') => {
+                self.bump();
+                let body = self.body();
+                if body.is_empty() {
+                    return Err(self.error(location, format!("{:?} has no command", keys)));
+                }
+                Action::Shell(body)
+            }
+            Some('{') => {
+                let opening = self.location();
+                self.bump();
+                Action::Mode(self.mode(config, Some(opening))?)
+            }
+            _ => return Err(self.error(location, format!("{:?} needs a : command or a {{ block }}", keys))),
+        };
+        Ok(Binding {
+            combo,
+            action,
+            on_release: false,
+            replay: false,
+        })
+    }
+
+    /// The shell snippet, quotes and brackets are respected when looking for its end.
+    fn body(&mut self) -> String {
+        let start = self.position;
+        let mut depth = 0;
+        let mut quote = None;
+        let mut escaped = false;
+        while let Some(c) = self.peek() {
+            if escaped {
+                escaped = false;
+                self.bump();
+                continue;
+            }
+            match (quote, c) {
+                (Some('\''), '\'') => quote = None,
+                (Some('\''), _) => {}
+                (_, '\\') => escaped = true,
+                (Some(_), '"') => quote = None,
+                (Some(_), _) => {}
+                (None, '\'' | '"') => quote = Some(c),
+                (None, '{' | '(') => depth += 1,
+                (None, '}' | ')') if depth > 0 => depth -= 1,
+                (None, '}' | ';' | '\n') if depth == 0 => break,
+                _ => {}
+            }
+            self.bump();
+        }
+        self.text[start..self.position].trim().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Vec<String> {
+        let config = parse_dsl(text).unwrap();
+        config.describe(&config.root)
+    }
+
+    fn error(text: &str) -> String {
+        match parse_dsl(text) {
+            Ok(_) => panic!("{:?} is parsed", text),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn blocks() {
+        let text = "# network
+super_l + c {
+    shift + n: nm-connection-editor
+    n: urxvt -e nmtui
+    a { a: pavucontrol; j: pw-jack catia }
+}
+super_l + r: { bspc wm -r; notify-send reloaded; }";
+        assert_eq!(
+            parse(text),
+            [
+                "c+super_l { n+shift: nm-connection-editor, n: urxvt -e nmtui, a { a: pavucontrol, j: pw-jack catia } }",
+                "r+super_l: { bspc wm -r; notify-send reloaded; }",
+            ]
+        );
+    }
+
+    #[test]
+    fn separators_and_comments() {
+        assert_eq!(
+            parse("# comment\n\n;; a: echo a # for sh\nb: echo b; c: echo c;\n  # indented comment"),
+            ["a: echo a # for sh", "b: echo b", "c: echo c"]
+        );
+        assert!(parse("").is_empty());
+    }
+
+    #[test]
+    fn quotes_and_brackets() {
+        assert_eq!(
+            parse(r#"a: echo '}' "a;b" \; (cd /tmp; ls) $(date; true)"#),
+            [r#"a: echo '}' "a;b" \; (cd /tmp; ls) $(date; true)"#]
+        );
+        assert_eq!(
+            parse(r#"m { a: echo "it's }"; b: echo '"' }"#),
+            [r#"m { a: echo "it's }", b: echo '"' }"#]
+        );
+    }
+
+    #[test]
+    fn body_ends_with_its_block() {
+        assert_eq!(parse("m { a: echo {x,y} }"), ["m { a: echo {x,y} }"]);
+        assert_eq!(parse("m { n { a: echo } }"), ["m { n { a: echo } }"]);
+    }
+
+    #[test]
+    fn unclosed_block() {
+        assert_eq!(
+            error("a: echo\n  b {\n    c: echo"),
+            "Syntax error: line 2, column 5: the block is not closed with }"
+        );
+    }
+
+    #[test]
+    fn unexpected_brace() {
+        assert_eq!(error("a: echo\n  }"), "Syntax error: line 2, column 3: unexpected }");
+        // the column is counted in characters
+        assert_eq!(error("a: echo é; }"), "Syntax error: line 1, column 12: unexpected }");
+    }
+
+    #[test]
+    fn missing_command() {
+        assert_eq!(error("a: echo\n  b:"), "Syntax error: line 2, column 3: \"b\" has no command");
+        assert_eq!(
+            error("a: echo\n\nb"),
+            "Syntax error: line 3, column 1: \"b\" needs a : command or a { block }"
+        );
+    }
+
+    #[test]
+    fn bad_keys() {
+        assert_eq!(
+            error("a: x\n   no_such_key: x"),
+            "Syntax error: line 2, column 4: Unknown key name \"no_such_key\""
+        );
+        assert_eq!(
+            error("m { + a: x }"),
+            "Syntax error: line 1, column 5: Invalid binding \"+ a\": empty key name"
+        );
+    }
+}
@@ -0,0 +1,26 @@
+use std::{io, path::PathBuf};
+
+/// An error that raises when the bindings can't be loaded.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Syntax(String),
+    Script(String),
+    UnknownKey(String),
+    InvalidBinding { keys: String, details: String },
+}
+
+impl std::error::Error for ConfigError {}
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "Could not read {:?}: {}", path, err),
+            ConfigError::Syntax(details) => write!(f, "Syntax error: {}", details),
+            ConfigError::Script(details) => write!(f, "Script error: {}", details),
+            ConfigError::UnknownKey(name) => write!(f, "Unknown key name {:?}", name),
+            ConfigError::InvalidBinding { keys, details } => {
+                write!(f, "Invalid binding {:?}: {}", keys, details)
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::ffi::CString;
+
+use crate::display::Keysym;
+
+/// Short names that are not keysym names on their own
+const ALIASES: &[(&str, &str)] = &[
+    ("super", "Super_L"),
+    ("shift", "Shift_L"),
+    ("ctrl", "Control_L"),
+    ("control", "Control_L"),
+    ("alt", "Alt_L"),
+    ("esc", "Escape"),
+    ("enter", "Return"),
+];
+
+/// Finds the keysym by its name, like `"super_l"` or `"XF86AudioRaiseVolume"`.
+///
+/// The X keysym names are case sensitive (`Super_L`),
+/// so the capitalized form is tried when the name is not found as it is.
+pub fn keysym_from_name(name: &str) -> Option<Keysym> {
+    let name = ALIASES
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+        .map_or(name, |(_, keysym_name)| keysym_name);
+    string_to_keysym(name).or_else(|| string_to_keysym(&capitalize(name)))
+}
+
+fn string_to_keysym(name: &str) -> Option<Keysym> {
+    let name = CString::new(name).ok()?;
+    let keysym = unsafe { x11::xlib::XStringToKeysym(name.as_ptr()) };
+    if keysym == 0 {
+        None
+    } else {
+        Some(keysym as Keysym)
+    }
+}
+
+/// super_l -> Super_L
+fn capitalize(name: &str) -> String {
+    name.split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::{XK_Escape, XK_Return, XK_Super_L};
+
+    #[test]
+    fn keysym_names() {
+        assert_eq!(keysym_from_name("Super_L"), Some(XK_Super_L));
+        assert_eq!(keysym_from_name("Return"), Some(XK_Return));
+        assert_eq!(keysym_from_name("a"), Some(0x61));
+        assert_eq!(keysym_from_name("XF86AudioRaiseVolume"), Some(0x1008ff13));
+    }
+
+    #[test]
+    fn lowercase_names() {
+        assert_eq!(keysym_from_name("super_l"), Some(XK_Super_L));
+        assert_eq!(keysym_from_name("return"), Some(XK_Return));
+    }
+
+    #[test]
+    fn aliases() {
+        assert_eq!(keysym_from_name("super"), Some(XK_Super_L));
+        assert_eq!(keysym_from_name("ESC"), Some(XK_Escape));
+        assert_eq!(keysym_from_name("enter"), Some(XK_Return));
+    }
+
+    #[test]
+    fn unknown_names() {
+        assert_eq!(keysym_from_name("no_such_key"), None);
+        assert_eq!(keysym_from_name(""), None);
+        assert_eq!(keysym_from_name("a\0b"), None);
+    }
+
+    #[test]
+    fn capitalized() {
+        assert_eq!(capitalize("super_l"), "Super_L");
+        assert_eq!(capitalize("caps_lock"), "Caps_Lock");
+        assert_eq!(capitalize("a__b"), "A__B");
+    }
+}
@@ -0,0 +1,82 @@
+//! Bindings loaded at runtime, instead of compiling them into `main.rs`.
+//!
+//! Every config format is compiled into the same tree of modes,
+//! which is executed by a single node of the state machine.
+
+mod dsl;
+mod error;
+mod keysym;
+mod reload;
+mod runtime;
+#[cfg(feature = "scripting")]
+mod script;
+mod sxhkd;
+mod toml_file;
+mod tree;
+mod watch;
+
+pub use dsl::*;
+pub use error::*;
+pub use keysym::*;
+pub use reload::*;
+pub use runtime::*;
+#[cfg(feature = "scripting")]
+pub use script::*;
+pub use sxhkd::*;
+pub use toml_file::*;
+pub use tree::*;
+pub use watch::*;
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use crate::{display::DisplayServerClient, event_loop::EventLoop, state_machine::Executor};
+
+/// Executes the bindings of the config file, and reloads it when it changes.
+pub fn run(mut client: impl DisplayServerClient + 'static, path: PathBuf) -> ! {
+    let config = load(&path).unwrap_or_else(|err| {
+        println!("ERROR: {}", err);
+        std::process::exit(1)
+    });
+    let key_state = ConfigKeyState::grab(&mut client, &config);
+    let executor = Executor::with_key_state(key_state, config_node, ConfigState::new(config));
+    let mut event_loop = EventLoop::new(client, executor);
+    Reloader::new(path).register(&mut event_loop);
+    event_loop.run()
+}
+
+/// Reads the config file, its format is chosen by the extension:
+/// `.toml`, `.rhkd`, `.rhai` or anything else for an sxhkdrc.
+pub fn load(path: &Path) -> Result<Config, ConfigError> {
+    match path.extension() {
+        Some(extension) if extension == "toml" => load_toml(path),
+        Some(extension) if extension == "rhkd" => load_dsl(path),
+        Some(extension) if extension == "rhai" => load_script(path),
+        _ => load_sxhkd(path),
+    }
+}
+
+#[cfg(not(feature = "scripting"))]
+fn load_script(path: &Path) -> Result<Config, ConfigError> {
+    Err(ConfigError::Script(format!(
+        "{:?} can't be run, rhkd is built without the scripting feature",
+        path
+    )))
+}
+
+/// The config file given by `--config <path>`,
+/// or `$XDG_CONFIG_HOME/rhkd/rhkd.toml` if it exists.
+pub fn config_path() -> Option<PathBuf> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" || arg == "-c" {
+            return args.next().map(PathBuf::from);
+        }
+    }
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("rhkd").join("rhkd.toml")).filter(|path| path.exists())
+}
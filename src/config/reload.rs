@@ -0,0 +1,74 @@
+use std::{
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{display, event_loop::EventLoop, state_machine::Executor};
+
+use super::{load, ConfigError, ConfigKeyState, ConfigState, FileWatcher};
+
+/// Loads the config file again on SIGHUP or when the file is saved.
+pub struct Reloader {
+    path: PathBuf,
+    watcher: Option<FileWatcher>,
+}
+
+impl Reloader {
+    pub fn new(path: PathBuf) -> Self {
+        let watcher = FileWatcher::new(&path)
+            .map_err(|err| println!("WARN: {:?} won't be reloaded on change: {}", path, err))
+            .ok();
+        Self { path, watcher }
+    }
+
+    /// Adds the signal and the file watcher to the event sources.
+    pub fn register<C: display::DisplayServerClient + 'static>(
+        self,
+        event_loop: &mut EventLoop<C, ConfigKeyState, ConfigState>,
+    ) {
+        let reloader = Rc::new(self);
+        let on_signal = reloader.clone();
+        if let Err(err) = event_loop.add_signal(libc::SIGHUP, move |executor, client| {
+            on_signal.reload_now(executor, client)
+        }) {
+            println!("WARN: {:?} won't be reloaded on SIGHUP: {}", reloader.path, err);
+        }
+        if let Some(fd) = reloader.watcher.as_ref().map(FileWatcher::fd) {
+            let on_change = reloader.clone();
+            if let Err(err) = event_loop.add_source(fd, move |executor, client| {
+                if on_change.watcher.as_ref().is_some_and(FileWatcher::changed) {
+                    on_change.reload_now(executor, client)
+                }
+            }) {
+                println!("WARN: {:?} won't be reloaded on change: {}", reloader.path, err);
+            }
+        }
+    }
+
+    fn reload_now(
+        &self,
+        executor: &mut Executor<ConfigKeyState, ConfigState>,
+        client: &mut dyn display::DisplayServerClient,
+    ) {
+        match reload(&self.path, executor, client) {
+            Ok(()) => println!("reloaded {:?}", self.path),
+            Err(err) => println!("WARN: {:?} is not reloaded, the old bindings stay: {}", self.path, err),
+        }
+    }
+}
+
+/// Swaps the bindings without dropping the connection.
+///
+/// The new config is validated first, nothing changes if it is invalid.
+pub fn reload(
+    path: &Path,
+    executor: &mut Executor<ConfigKeyState, ConfigState>,
+    client: &mut dyn display::DisplayServerClient,
+) -> Result<(), ConfigError> {
+    let config = load(path)?;
+    executor.key_state_mut().ungrab(client);
+    *executor.key_state_mut() = ConfigKeyState::grab(client, &config);
+    *executor.user_state_mut() = ConfigState::new(config);
+    executor.restart(client);
+    Ok(())
+}
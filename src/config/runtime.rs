@@ -0,0 +1,478 @@
+use std::collections::HashSet;
+
+use crate::{
+    display,
+    state_machine::{grab_keysym, ungrab_keycodes, ManageKeyState, NodeOut, SIZE_OF_THE_ACTIVE_KEYS},
+};
+
+use super::{Action, Config, KeyId};
+
+/// The same as the `KeyState` generated by `define_keys!`,
+/// but the keys are known only at runtime.
+pub struct ConfigKeyState {
+    keycodes: HashSet<display::Keycode>,
+    modifiers: display::Modifiers,
+    /// the last event was a key press, the bindings are triggered only by presses
+    pressing: bool,
+    /// the key released by the last event, for the release bindings
+    released: Option<display::Keycode>,
+    /// keysyms of every key, indexed by `KeyId`
+    keysyms: Vec<display::Keysym>,
+    /// the keys of the top-level combos with their modifier masks, only these are grabbed
+    grabs: HashSet<(KeyId, u16)>,
+    /// keycodes of every key, indexed by `KeyId`
+    keys: Vec<Vec<display::Keycode>>,
+}
+
+impl ConfigKeyState {
+    pub fn grab(client: &mut dyn display::DisplayServerClient, config: &Config) -> Self {
+        let keysyms: Vec<_> = config.keys.iter().map(|(_, keysym)| *keysym).collect();
+        let grabs = config
+            .root
+            .bindings
+            .iter()
+            .flat_map(|binding| combo_grabs(&keysyms, &binding.combo))
+            .collect();
+        let mut key_state = Self {
+            keycodes: HashSet::with_capacity(SIZE_OF_THE_ACTIVE_KEYS),
+            modifiers: display::Modifiers::default(),
+            pressing: false,
+            released: None,
+            keysyms,
+            grabs,
+            keys: vec![],
+        };
+        key_state.lookup_keys(client);
+        key_state
+    }
+
+    /// Releases every key grabbed by `ConfigKeyState::grab`.
+    pub fn ungrab(&self, client: &mut dyn display::DisplayServerClient) {
+        for (id, modifiers) in &self.grabs {
+            ungrab_keycodes(client, &self.keys[*id], *modifiers);
+        }
+        client.flush();
+    }
+
+    /// Grabs the keys of the top-level combos, the others are only looked up,
+    /// they are received through the keyboard grab of the modes.
+    fn lookup_keys(&mut self, client: &mut dyn display::DisplayServerClient) {
+        self.keys = self.keysyms.iter().map(|keysym| client.keycodes(*keysym)).collect();
+        for (id, modifiers) in &self.grabs {
+            grab_keysym(client, self.keysyms[*id], *modifiers);
+        }
+    }
+
+    /// A modifier key counts as pressed while its modifier is held,
+    /// since it is not grabbed when it is part of a combo.
+    pub fn pressed(&self, key: KeyId) -> bool {
+        let held = display::Modifier::of_keysym(self.keysyms[key])
+            .is_some_and(|modifier| self.modifiers.mask() & modifier as u16 != 0);
+        let codes = &self.keys[key];
+        // a keysym missing from the layout has no keycodes, it's never pressed
+        held || (!codes.is_empty() && codes.iter().all(|code| self.keycodes.contains(code)))
+    }
+
+    /// The last event released the key, the keys of its combo are still held.
+    pub fn released(&self, key: KeyId) -> bool {
+        self.released.is_some_and(|keycode| self.keys[key].contains(&keycode))
+    }
+
+    pub fn modifiers(&self) -> display::Modifiers {
+        self.modifiers
+    }
+}
+
+/// The grabs of a top-level combo, like `super + shift + n`:
+/// the modifier keys become the mask of the other keys,
+/// a combo of only modifier keys grabs them with any modifiers.
+fn combo_grabs(keysyms: &[display::Keysym], combo: &[KeyId]) -> Vec<(KeyId, u16)> {
+    let (modifiers, keys): (Vec<_>, Vec<_>) = combo
+        .iter()
+        .map(|id| (*id, display::Modifier::of_keysym(keysyms[*id])))
+        .partition(|(_, modifier)| modifier.is_some());
+    if keys.is_empty() {
+        let any = display::Modifier::Any as u16;
+        return modifiers.into_iter().map(|(id, _)| (id, any)).collect();
+    }
+    let mask = modifiers
+        .iter()
+        .filter_map(|(_, modifier)| *modifier)
+        .fold(0, |mask, modifier| mask | modifier as u16);
+    keys.into_iter().map(|(id, _)| (id, mask)).collect()
+}
+
+impl ManageKeyState for ConfigKeyState {
+    /// No keys are grabbed, use `ConfigKeyState::grab`.
+    fn new(_client: &mut dyn display::DisplayServerClient) -> Self {
+        Self {
+            keycodes: HashSet::with_capacity(SIZE_OF_THE_ACTIVE_KEYS),
+            modifiers: display::Modifiers::default(),
+            pressing: false,
+            released: None,
+            keysyms: vec![],
+            grabs: HashSet::new(),
+            keys: vec![],
+        }
+    }
+
+    fn regrab(&mut self, client: &mut dyn display::DisplayServerClient) {
+        for (id, modifiers) in &self.grabs {
+            ungrab_keycodes(client, &self.keys[*id], *modifiers);
+        }
+        self.keycodes.clear();
+        self.lookup_keys(client);
+    }
+
+    fn resync(&mut self, pressed: &[display::Keycode]) {
+        self.keycodes = pressed.iter().copied().collect();
+    }
+
+    fn update(&mut self, e: display::DisplayServerEvent) {
+        match e {
+            display::DisplayServerEvent::KeyRelease(k, modifiers) => {
+                self.modifiers = modifiers;
+                self.pressing = false;
+                self.released = Some(k);
+                self.keycodes.remove(&k);
+            }
+            // like sxhkd, the autorepeat runs the command again
+            display::DisplayServerEvent::KeyPress(k, modifiers)
+            | display::DisplayServerEvent::KeyRepeat(k, modifiers) => {
+                self.modifiers = modifiers;
+                self.pressing = true;
+                self.released = None;
+                self.keycodes.insert(k);
+            }
+            _ => {
+                self.pressing = false;
+                self.released = None;
+            }
+        }
+    }
+}
+
+/// The user state of `config_node`
+pub struct ConfigState {
+    pub config: Config,
+    /// the entered sub-modes, see `Config::mode`
+    pub mode: Vec<usize>,
+}
+
+impl ConfigState {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            mode: vec![],
+        }
+    }
+}
+
+/// The start node of a `Config`, the entered sub-modes are tracked in the state.
+pub fn config_node(
+    key: &ConfigKeyState,
+    state: &mut ConfigState,
+) -> NodeOut<ConfigKeyState, ConfigState> {
+    // coming back by a timeout or a restart
+    state.mode.clear();
+    handle_key(key, state)
+}
+
+/// The node of the sub-modes, the executor grabs the keyboard while it is not in the start node.
+fn mode_node(key: &ConfigKeyState, state: &mut ConfigState) -> NodeOut<ConfigKeyState, ConfigState> {
+    handle_key(key, state)
+}
+
+fn handle_key(key: &ConfigKeyState, state: &mut ConfigState) -> NodeOut<ConfigKeyState, ConfigState> {
+    // otherwise a held modifier would trigger its binding again on every release
+    if !key.pressing && key.released.is_none() {
+        return NodeOut::None;
+    }
+    if key.pressing && !state.mode.is_empty() && key.pressed(state.config.escape) {
+        state.mode.clear();
+        return transition(state);
+    }
+    let mode = state.config.mode(&state.mode);
+    // the most specific combo wins, so super+shift+q is not taken by super+q,
+    // a release binding fires only on the release of a key of its combo
+    let matching = mode
+        .bindings
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, binding)| match (key.pressing, binding.on_release) {
+            (true, _) => binding.combo.iter().all(|id| key.pressed(*id)),
+            (false, true) => {
+                binding.combo.iter().any(|id| key.released(*id))
+                    && binding.combo.iter().all(|id| key.pressed(*id) || key.released(*id))
+            }
+            (false, false) => false,
+        })
+        .max_by_key(|(_, binding)| binding.combo.len());
+    let (index, binding) = match matching {
+        Some(matching) => matching,
+        None => return NodeOut::None,
+    };
+    let next = if key.pressing && binding.on_release {
+        // the press is hidden, so the release comes here, not to the focused window
+        transition(state)
+    } else {
+        match &binding.action {
+            Action::Mode(_) => state.mode.push(index),
+            action => {
+                action.run();
+                if !mode.locked {
+                    state.mode.clear();
+                }
+            }
+        }
+        transition(state)
+    };
+    if binding.replay {
+        next.with_replay()
+    } else {
+        next
+    }
+}
+
+/// Goes to `config_node` at the top level, or to `mode_node` in a sub-mode,
+/// and sets the timeout of the entered mode.
+fn transition(state: &ConfigState) -> NodeOut<ConfigKeyState, ConfigState> {
+    if state.mode.is_empty() {
+        return NodeOut::Next(config_node);
+    }
+    let next = NodeOut::Next(mode_node);
+    match state.config.mode(&state.mode).timeout {
+        Some(after) => next.with_timeout(after, NodeOut::Next(config_node)),
+        None => next,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, time::Duration};
+
+    use super::*;
+    use crate::{
+        config::{Binding, Mode},
+        display::{DisplayServerEvent, Keycode, Modifier, Modifiers},
+    };
+
+    /// The commands that ran, by name.
+    type Log = Rc<RefCell<Vec<&'static str>>>;
+
+    fn callback(log: &Log, name: &'static str) -> Action {
+        let log = log.clone();
+        Action::Callback(Rc::new(move || log.borrow_mut().push(name)))
+    }
+
+    fn binding(config: &mut Config, keys: &str, action: Action) -> Binding {
+        Binding {
+            combo: config.combo(keys).unwrap(),
+            action,
+            on_release: false,
+            replay: false,
+        }
+    }
+
+    /// Every key gets the keycode of its id + 10, without a display server.
+    fn key_state(config: &Config) -> ConfigKeyState {
+        ConfigKeyState {
+            keycodes: HashSet::new(),
+            modifiers: Modifiers::default(),
+            pressing: false,
+            released: None,
+            keysyms: config.keys.iter().map(|(_, keysym)| *keysym).collect(),
+            grabs: HashSet::new(),
+            keys: (0..config.keys.len()).map(|id| vec![id as Keycode + 10]).collect(),
+        }
+    }
+
+    fn keycode(config: &Config, name: &str) -> Keycode {
+        let id = config.keys.iter().position(|(known, _)| known == name).unwrap();
+        id as Keycode + 10
+    }
+
+    fn mask(modifiers: &[Modifier]) -> Modifiers {
+        Modifiers::from_mask(modifiers.iter().fold(0, |mask, modifier| mask | *modifier as u16))
+    }
+
+    struct Keyboard {
+        key_state: ConfigKeyState,
+        state: ConfigState,
+    }
+
+    impl Keyboard {
+        fn new(config: Config) -> Self {
+            Self {
+                key_state: key_state(&config),
+                state: ConfigState::new(config),
+            }
+        }
+
+        fn event(&mut self, e: DisplayServerEvent) -> NodeOut<ConfigKeyState, ConfigState> {
+            self.key_state.update(e);
+            handle_key(&self.key_state, &mut self.state)
+        }
+
+        fn press(&mut self, name: &str, modifiers: &[Modifier]) -> NodeOut<ConfigKeyState, ConfigState> {
+            let keycode = keycode(&self.state.config, name);
+            self.event(DisplayServerEvent::KeyPress(keycode, mask(modifiers)))
+        }
+
+        fn release(&mut self, name: &str, modifiers: &[Modifier]) -> NodeOut<ConfigKeyState, ConfigState> {
+            let keycode = keycode(&self.state.config, name);
+            self.event(DisplayServerEvent::KeyRelease(keycode, mask(modifiers)))
+        }
+    }
+
+    #[test]
+    fn grabs_of_combos() {
+        let mut config = Config::new();
+        let super_l = config.key("Super_L").unwrap();
+        let shift = config.key("Shift_L").unwrap();
+        let n = config.key("n").unwrap();
+        let keysyms: Vec<_> = config.keys.iter().map(|(_, keysym)| *keysym).collect();
+        let any = Modifier::Any as u16;
+        assert_eq!(combo_grabs(&keysyms, &[n]), [(n, 0)]);
+        assert_eq!(combo_grabs(&keysyms, &[super_l, n]), [(n, Modifier::Mod4 as u16)]);
+        assert_eq!(
+            combo_grabs(&keysyms, &[super_l, shift, n]),
+            [(n, Modifier::Mod4 as u16 | Modifier::Shift as u16)]
+        );
+        // only modifiers, they are grabbed with any modifiers
+        assert_eq!(combo_grabs(&keysyms, &[super_l]), [(super_l, any)]);
+        assert_eq!(combo_grabs(&keysyms, &[super_l, shift]), [(super_l, any), (shift, any)]);
+    }
+
+    #[test]
+    fn pressed_keys() {
+        let mut config = Config::new();
+        let super_l = config.key("Super_L").unwrap();
+        let a = config.key("a").unwrap();
+        let mut key_state = key_state(&config);
+        key_state.update(DisplayServerEvent::KeyPress(a as Keycode + 10, Modifiers::default()));
+        assert!(key_state.pressed(a));
+        assert!(!key_state.pressed(super_l));
+        // a modifier key is pressed while its modifier is held
+        key_state.update(DisplayServerEvent::KeyPress(a as Keycode + 10, mask(&[Modifier::Mod4])));
+        assert!(key_state.pressed(super_l));
+        key_state.update(DisplayServerEvent::KeyRelease(a as Keycode + 10, Modifiers::default()));
+        assert!(!key_state.pressed(a));
+        assert!(key_state.released(a));
+    }
+
+    #[test]
+    fn key_missing_from_the_layout() {
+        let mut config = Config::new();
+        let a = config.key("a").unwrap();
+        let mut key_state = key_state(&config);
+        key_state.keys[a].clear();
+        key_state.update(DisplayServerEvent::KeyPress(99, Modifiers::default()));
+        assert!(!key_state.pressed(a));
+    }
+
+    #[test]
+    fn the_most_specific_combo_wins() {
+        let log = Log::default();
+        let mut config = Config::new();
+        let bindings = vec![
+            binding(&mut config, "super + q", callback(&log, "super+q")),
+            binding(&mut config, "super + shift + q", callback(&log, "super+shift+q")),
+            binding(&mut config, "q", callback(&log, "q")),
+        ];
+        config.root.bindings = bindings;
+        let mut keyboard = Keyboard::new(config);
+        assert!(matches!(keyboard.press("q", &[Modifier::Mod4, Modifier::Shift]), NodeOut::Next(_)));
+        keyboard.release("q", &[Modifier::Mod4, Modifier::Shift]);
+        keyboard.press("q", &[Modifier::Mod4]);
+        keyboard.release("q", &[Modifier::Mod4]);
+        keyboard.press("q", &[]);
+        // a release doesn't fire a press binding
+        keyboard.release("q", &[Modifier::Mod4]);
+        assert_eq!(*log.borrow(), ["super+shift+q", "super+q", "q"]);
+    }
+
+    #[test]
+    fn unbound_keys() {
+        let log = Log::default();
+        let mut config = Config::new();
+        config.root.bindings = vec![binding(&mut config, "super + q", callback(&log, "super+q"))];
+        config.key("x").unwrap();
+        let mut keyboard = Keyboard::new(config);
+        assert!(matches!(keyboard.press("q", &[]), NodeOut::None));
+        assert!(matches!(keyboard.release("q", &[]), NodeOut::None));
+        assert!(matches!(keyboard.press("x", &[Modifier::Mod4]), NodeOut::None));
+        assert!(matches!(keyboard.release("x", &[Modifier::Mod4]), NodeOut::None));
+        assert!(log.borrow().is_empty());
+    }
+
+    #[test]
+    fn release_bindings() {
+        let log = Log::default();
+        let mut config = Config::new();
+        let mut release = binding(&mut config, "super + t", callback(&log, "super+@t"));
+        release.on_release = true;
+        config.root.bindings = vec![release];
+        config.key("x").unwrap();
+        let mut keyboard = Keyboard::new(config);
+        // the press is taken without running the command, so it's hidden
+        assert!(matches!(keyboard.press("t", &[Modifier::Mod4]), NodeOut::Next(_)));
+        assert!(log.borrow().is_empty());
+        // another key released meanwhile is not the combo
+        keyboard.press("x", &[Modifier::Mod4]);
+        assert!(matches!(keyboard.release("x", &[Modifier::Mod4]), NodeOut::None));
+        assert!(matches!(keyboard.release("t", &[Modifier::Mod4]), NodeOut::Next(_)));
+        assert_eq!(*log.borrow(), ["super+@t"]);
+    }
+
+    #[test]
+    fn replayed_bindings() {
+        let log = Log::default();
+        let mut config = Config::new();
+        let mut replayed = binding(&mut config, "Print", callback(&log, "print"));
+        replayed.replay = true;
+        config.root.bindings = vec![replayed];
+        let mut keyboard = Keyboard::new(config);
+        assert!(matches!(keyboard.press("Print", &[]), NodeOut::Replay(_)));
+        assert_eq!(*log.borrow(), ["print"]);
+    }
+
+    #[test]
+    fn modes() {
+        let log = Log::default();
+        let mut config = Config::new();
+        let mut sub_mode = Mode {
+            timeout: Some(Duration::from_millis(500)),
+            ..Mode::default()
+        };
+        sub_mode.bindings = vec![binding(&mut config, "n", callback(&log, "n"))];
+        let mut locked = Mode {
+            locked: true,
+            ..Mode::default()
+        };
+        locked.bindings = vec![binding(&mut config, "r", callback(&log, "r"))];
+        config.root.bindings = vec![
+            binding(&mut config, "super + c", Action::Mode(sub_mode)),
+            binding(&mut config, "super + o", Action::Mode(locked)),
+        ];
+        let mut keyboard = Keyboard::new(config);
+
+        assert!(matches!(keyboard.press("c", &[Modifier::Mod4]), NodeOut::Timeout { .. }));
+        assert_eq!(keyboard.state.mode, [0]);
+        // a key of the top level is not bound in the mode
+        keyboard.release("c", &[Modifier::Mod4]);
+        assert!(matches!(keyboard.press("n", &[]), NodeOut::Next(_)));
+        assert!(keyboard.state.mode.is_empty());
+
+        keyboard.press("o", &[Modifier::Mod4]);
+        assert_eq!(keyboard.state.mode, [1]);
+        keyboard.press("r", &[]);
+        keyboard.press("r", &[]);
+        // a locked mode is left only by escape
+        assert_eq!(keyboard.state.mode, [1]);
+        assert!(matches!(keyboard.press("Escape", &[]), NodeOut::Next(_)));
+        assert!(keyboard.state.mode.is_empty());
+        assert_eq!(*log.borrow(), ["n", "r", "r"]);
+    }
+}
@@ -0,0 +1,272 @@
+use std::{cell::RefCell, fs, path::Path, rc::Rc, time::Duration};
+
+use rhai::{
+    Array, Dynamic, Engine, EvalAltResult, FnPtr, ImmutableString, Map, NativeCallContext, Scope, AST,
+};
+
+use crate::{process, tools};
+
+use super::{Action, Binding, Config, ConfigError, Mode};
+
+/// A mode as it is built by the script, the key names are resolved after the script has run.
+#[derive(Clone, Default)]
+struct ScriptMode {
+    bindings: Vec<(String, ScriptAction)>,
+    locked: bool,
+    timeout: Option<Duration>,
+}
+
+#[derive(Clone)]
+enum ScriptAction {
+    Shell(String),
+    Exec(Array),
+    Callback(FnPtr),
+    Mode(ScriptMode),
+}
+
+/// Runs a Rhai script and compiles the bindings it has registered.
+pub fn load_script(path: &Path) -> Result<Config, ConfigError> {
+    let text = fs::read_to_string(path).map_err(|err| ConfigError::Io(path.into(), err))?;
+    parse_script(&text)
+}
+
+/// An example:
+/// ```text
+/// // shared by every callback, it is kept between the key presses
+/// state.count = 0;
+///
+/// bind("super_l + c", mode()
+///     .timeout(2000)
+///     .bind("shift + n", "nm-connection-editor")
+///     .bind("n", ["urxvt", "-e", "nmtui"])
+///     .bind("a", mode()
+///         .bind("a", "pavucontrol")));
+///
+/// bind("iacute", || {
+///     state.count += 1;
+///     print(`counter is increased: ${state.count}`);
+/// });
+///
+/// bind("super_l + XF86AudioNext", || cycle_sinks(false));
+/// bind("super_l + Return", || context_run("urxvt",
+///     |window_name| urxvt().path(window_name).run(),
+///     || urxvt().run()));
+/// ```
+pub fn parse_script(text: &str) -> Result<Config, ConfigError> {
+    let root = Rc::new(RefCell::new(ScriptMode::default()));
+    let mut engine = Engine::new();
+    register_bindings(&mut engine, &root);
+    register_tools(&mut engine);
+
+    let ast = engine
+        .compile(text)
+        .map_err(|err| ConfigError::Syntax(err.to_string()))?;
+    let mut scope = Scope::new();
+    scope.push("state", Map::new());
+    engine
+        .run_ast_with_scope(&mut scope, &ast)
+        .map_err(|err| ConfigError::Script(err.to_string()))?;
+
+    let runtime = Rc::new((engine, ast));
+    let mut config = Config::new();
+    let root = root.take();
+    config.root = compile_mode(&mut config, root, &runtime)?;
+    Ok(config)
+}
+
+fn compile_mode(
+    config: &mut Config,
+    mode: ScriptMode,
+    runtime: &Rc<(Engine, AST)>,
+) -> Result<Mode, ConfigError> {
+    let mut bindings = vec![];
+    for (keys, action) in mode.bindings {
+        let combo = config.combo(&keys)?;
+        let action = match action {
+            ScriptAction::Shell(command) => Action::Shell(command),
+            ScriptAction::Exec(argv) if argv.is_empty() => {
+                return Err(ConfigError::InvalidBinding {
+                    keys,
+                    details: "the command is empty".into(),
+                })
+            }
+            ScriptAction::Exec(argv) => Action::Exec(argv.iter().map(Dynamic::to_string).collect()),
+            ScriptAction::Callback(callback) => {
+                let runtime = runtime.clone();
+                Action::Callback(Rc::new(move || {
+                    let (engine, ast) = &*runtime;
+                    if let Err(err) = callback.call::<Dynamic>(engine, ast, ()) {
+                        println!("WARN: the callback of {} failed: {}", keys, err);
+                    }
+                }))
+            }
+            ScriptAction::Mode(sub_mode) => Action::Mode(compile_mode(config, sub_mode, runtime)?),
+        };
+        bindings.push(Binding {
+            combo,
+            action,
+            on_release: false,
+            replay: false,
+        });
+    }
+    Ok(Mode {
+        bindings,
+        locked: mode.locked,
+        timeout: mode.timeout,
+    })
+}
+
+/// `bind(keys, action)` adds to the root mode, `mode().bind(keys, action)` builds a sub-mode,
+/// the action is a shell command, an argv array, a closure or a sub-mode.
+fn register_bindings(engine: &mut Engine, root: &Rc<RefCell<ScriptMode>>) {
+    let root = root.clone();
+    engine
+        .register_type_with_name::<ScriptMode>("Mode")
+        .register_fn("mode", ScriptMode::default)
+        .register_fn("locked", |mode: &mut ScriptMode| {
+            mode.locked = true;
+            mode.clone()
+        })
+        .register_fn("timeout", |mode: &mut ScriptMode, milliseconds: i64| {
+            mode.timeout = Some(Duration::from_millis(milliseconds.max(0) as u64));
+            mode.clone()
+        })
+        .register_fn("bind", move |keys: &str, action: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            let action = script_action(action)?;
+            root.borrow_mut().bindings.push((keys.into(), action));
+            Ok(())
+        })
+        .register_fn(
+            "bind",
+            |mode: &mut ScriptMode, keys: &str, action: Dynamic| -> Result<ScriptMode, Box<EvalAltResult>> {
+                mode.bindings.push((keys.into(), script_action(action)?));
+                Ok(mode.clone())
+            },
+        );
+}
+
+fn script_action(action: Dynamic) -> Result<ScriptAction, Box<EvalAltResult>> {
+    if action.is::<ImmutableString>() {
+        Ok(ScriptAction::Shell(action.cast::<ImmutableString>().into()))
+    } else if action.is::<Array>() {
+        Ok(ScriptAction::Exec(action.cast()))
+    } else if action.is::<FnPtr>() {
+        Ok(ScriptAction::Callback(action.cast()))
+    } else if action.is::<ScriptMode>() {
+        Ok(ScriptAction::Mode(action.cast()))
+    } else {
+        Err(format!("{} can't be bound, only a string, an array, a closure or a mode", action.type_name()).into())
+    }
+}
+
+/// The helpers of the `tools` module
+fn register_tools(engine: &mut Engine) {
+    engine
+        .register_fn("run", |command: &str| process::spawn_shell(command))
+        .register_fn("exec", |argv: Array| {
+            process::spawn(&argv.iter().map(Dynamic::to_string).collect::<Vec<_>>())
+        })
+        .register_fn("run_in_shell", |command: &str| {
+            tools::run_in_shell(command).unwrap_or_default()
+        })
+        .register_fn("cycle_sinks", tools::cycle_sinks)
+        .register_fn(
+            "context_run",
+            |context: NativeCallContext, process_name: &str, on_focus: FnPtr, otherwise: FnPtr| {
+                let report = |result: Result<Dynamic, _>| {
+                    if let Err(err) = result {
+                        println!("WARN: context_run callback failed: {}", err);
+                    }
+                };
+                tools::context_run(
+                    process_name,
+                    |window_name| report(on_focus.call_within_context(&context, (window_name.to_string(),))),
+                    || report(otherwise.call_within_context(&context, ())),
+                )
+            },
+        )
+        .register_type_with_name::<tools::Urxvt>("Urxvt")
+        .register_fn("urxvt", tools::Urxvt::new)
+        .register_fn("path", |urxvt: &mut tools::Urxvt, path: &str| urxvt.clone().path(path))
+        .register_fn("bg", |urxvt: &mut tools::Urxvt, r: i64, g: i64, b: i64| {
+            urxvt.clone().bg(tools::Color((r as u8, g as u8, b as u8)))
+        })
+        .register_fn("run", |urxvt: &mut tools::Urxvt| urxvt.clone().run());
+}
+
+#[cfg(all(test, feature = "scripting"))]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Vec<String> {
+        let config = parse_script(text).unwrap();
+        config.describe(&config.root)
+    }
+
+    fn error(text: &str) -> String {
+        match parse_script(text) {
+            Ok(_) => panic!("{:?} is parsed", text),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn bindings() {
+        let text = r#"
+            bind("super_l + c", mode()
+                .timeout(2000)
+                .bind("shift + n", "nm-connection-editor")
+                .bind("n", ["urxvt", "-e", "nmtui"])
+                .bind("a", mode()
+                    .locked()
+                    .bind("a", "pavucontrol")));
+            bind("iacute", || print("pressed"));
+        "#;
+        assert_eq!(
+            parse(text),
+            [
+                r#"c+super_l 2s { n+shift: nm-connection-editor, n: ["urxvt", "-e", "nmtui"], a locked { a: pavucontrol } }"#,
+                "iacute: <callback>",
+            ]
+        );
+    }
+
+    #[test]
+    fn bindings_made_by_code() {
+        let text = r#"
+            for n in 1..=3 {
+                bind(`super_l + ${n}`, `bspc desktop -f ^${n}`);
+            }
+        "#;
+        assert_eq!(
+            parse(text),
+            ["1+super_l: bspc desktop -f ^1", "2+super_l: bspc desktop -f ^2", "3+super_l: bspc desktop -f ^3"]
+        );
+    }
+
+    #[test]
+    fn syntax_error() {
+        assert!(error("bind(\"a\", ").starts_with("Syntax error: "));
+    }
+
+    #[test]
+    fn script_errors() {
+        assert!(error("throw \"no bindings\";").starts_with("Script error: "));
+        let err = error("bind(\"a\", 42);");
+        assert!(err.starts_with("Script error: "), "{}", err);
+        assert!(err.contains("i64 can't be bound"), "{}", err);
+    }
+
+    #[test]
+    fn bad_key_names() {
+        assert_eq!(error("bind(\"no_such_key\", \"x\");"), "Unknown key name \"no_such_key\"");
+        assert_eq!(
+            error("bind(\"super_l + \", \"x\");"),
+            "Invalid binding \"super_l + \": empty key name"
+        );
+        assert_eq!(
+            error("bind(\"a\", mode().bind(\"b\", []));"),
+            "Invalid binding \"b\": the command is empty"
+        );
+    }
+}
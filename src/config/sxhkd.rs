@@ -0,0 +1,479 @@
+use std::{fmt, fs, path::Path};
+
+use super::{Action, Binding, Config, ConfigError, KeyId, Mode};
+
+/// sxhkd modifier names, and the keys they are bound to here.
+///
+/// The left keys are used, because a combo is a set of pressed keys.
+const MODIFIERS: &[(&str, &str)] = &[
+    ("super", "Super_L"),
+    ("mod4", "Super_L"),
+    ("hyper", "Hyper_L"),
+    ("meta", "Meta_L"),
+    ("alt", "Alt_L"),
+    ("mod1", "Alt_L"),
+    ("control", "Control_L"),
+    ("ctrl", "Control_L"),
+    ("shift", "Shift_L"),
+    ("lock", "Caps_Lock"),
+    ("mode_switch", "Mode_switch"),
+];
+
+/// A construct of the sxhkdrc that is not supported, or supported differently.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Reads and compiles an sxhkdrc, the diagnostics are printed as warnings.
+pub fn load_sxhkd(path: &Path) -> Result<Config, ConfigError> {
+    let text = fs::read_to_string(path).map_err(|err| ConfigError::Io(path.into(), err))?;
+    let (config, diagnostics) = parse_sxhkd(&text)?;
+    for diagnostic in diagnostics {
+        println!("WARN: {:?} {}", path, diagnostic);
+    }
+    Ok(config)
+}
+
+/// Compiles the sxhkd syntax:
+/// ```text
+/// # brace expansion, the n-th combo runs the n-th command
+/// super + {h,j,k,l}
+///     bspc node -f {west,south,north,east}
+/// # chord chain: super+a, then b
+/// super + a ; b
+///     pavucontrol
+/// # modal chain: stays in the mode until escape
+/// super + o : {_,shift + } r
+///     bspc node -R {90,-90}
+/// # on the release of t, the press is hidden; ~ would replay it instead
+/// super + @t
+///     xdotool type hello
+/// ```
+pub fn parse_sxhkd(text: &str) -> Result<(Config, Vec<Diagnostic>), ConfigError> {
+    let mut config = Config::new();
+    let mut diagnostics = vec![];
+    for (line, hotkey, command) in hotkeys_and_commands(text, &mut diagnostics) {
+        let syntax_error = |details: String| ConfigError::Syntax(format!("line {}: {}", line, details));
+        let hotkeys = expand(&hotkey).map_err(syntax_error)?;
+        let mut commands = expand(&command).map_err(syntax_error)?;
+        if commands.len() == 1 {
+            commands = vec![commands[0].clone(); hotkeys.len()];
+        }
+        if commands.len() != hotkeys.len() {
+            return Err(syntax_error(format!(
+                "{} combos but {} commands",
+                hotkeys.len(),
+                commands.len()
+            )));
+        }
+        for (hotkey, command) in hotkeys.iter().zip(commands) {
+            let mut report = |message: String| diagnostics.push(Diagnostic { line, message });
+            if let Some(chain) = parse_chain(&mut config, hotkey, &mut report).map_err(syntax_error)? {
+                insert(&mut config.root, chain, command.trim().to_string());
+            }
+        }
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+    Ok((config, diagnostics))
+}
+
+/// Joins the continued lines and pairs every hotkey with its command,
+/// gives back the line number of the hotkey too.
+fn hotkeys_and_commands(text: &str, diagnostics: &mut Vec<Diagnostic>) -> Vec<(usize, String, String)> {
+    let mut logical_lines: Vec<(usize, String)> = vec![];
+    let mut continued = false;
+    for (index, line) in text.lines().enumerate() {
+        let (content, continues) = match line.strip_suffix('\\') {
+            Some(content) => (content, true),
+            None => (line, false),
+        };
+        match logical_lines.last_mut() {
+            Some((_, last)) if continued => last.push_str(content.trim_start()),
+            _ => logical_lines.push((index + 1, content.to_string())),
+        }
+        continued = continues;
+    }
+
+    let mut pairs = vec![];
+    let mut hotkey: Option<(usize, String)> = None;
+    for (line, content) in logical_lines {
+        if content.trim().is_empty() || content.trim_start().starts_with('#') {
+            continue;
+        }
+        let indented = content.starts_with(|c: char| c.is_whitespace());
+        match (indented, hotkey.take()) {
+            (true, Some((hotkey_line, hotkey))) => {
+                pairs.push((hotkey_line, hotkey, content.trim().to_string()))
+            }
+            (true, None) => diagnostics.push(Diagnostic {
+                line,
+                message: "command without a hotkey is ignored".into(),
+            }),
+            (false, previous) => {
+                if let Some((previous_line, _)) = previous {
+                    diagnostics.push(Diagnostic {
+                        line: previous_line,
+                        message: "hotkey without a command is ignored".into(),
+                    });
+                }
+                hotkey = Some((line, content.trim().to_string()));
+            }
+        }
+    }
+    if let Some((line, _)) = hotkey {
+        diagnostics.push(Diagnostic {
+            line,
+            message: "hotkey without a command is ignored".into(),
+        });
+    }
+    pairs
+}
+
+/// `a{b,c}d{e,f}` -> `abde`, `abdf`, `acde`, `acdf`
+///
+/// `_` is an empty item, `a-z` and `1-9` are ranges, `\{` is a literal brace,
+/// and the items can have groups of their own: `{a,b{c,d}}` -> `a`, `bc`, `bd`.
+fn expand(text: &str) -> Result<Vec<String>, String> {
+    let start = match find_unescaped(text, '{', 0) {
+        Some(start) => start,
+        None => return Ok(vec![unescape(text)]),
+    };
+    let end = find_closing(text, start).ok_or("unclosed brace")?;
+    let prefix = unescape(&text[..start]);
+    let suffixes = expand(&text[end + 1..])?;
+    let mut expanded = vec![];
+    for item in brace_items(&text[start + 1..end])? {
+        for suffix in &suffixes {
+            expanded.push(format!("{}{}{}", prefix, item, suffix));
+        }
+    }
+    Ok(expanded)
+}
+
+fn brace_items(group: &str) -> Result<Vec<String>, String> {
+    let mut items = vec![];
+    for item in split_items(group) {
+        let chars: Vec<char> = item.trim().chars().collect();
+        match chars.as_slice() {
+            ['_'] => items.push(String::new()),
+            [from, '-', to] if from.is_ascii_alphanumeric() && from <= to => {
+                items.extend((*from..=*to).map(String::from))
+            }
+            _ => items.extend(expand(item)?),
+        }
+    }
+    Ok(items)
+}
+
+/// Splits a group at its commas, the commas of the inner groups are kept.
+fn split_items(group: &str) -> Vec<&str> {
+    let mut items = vec![];
+    let mut depth = 0;
+    let mut escaped = false;
+    let mut item_start = 0;
+    for (index, c) in group.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '{' if !escaped => depth += 1,
+            '}' if !escaped => depth -= 1,
+            ',' if !escaped && depth == 0 => {
+                items.push(&group[item_start..index]);
+                item_start = index + 1;
+            }
+            _ => escaped = false,
+        }
+    }
+    items.push(&group[item_start..]);
+    items
+}
+
+fn find_unescaped(text: &str, needle: char, from: usize) -> Option<usize> {
+    let mut escaped = false;
+    for (index, c) in text[from..].char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            c if c == needle && !escaped => return Some(from + index),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+/// The `}` that closes the group opened at `start`.
+fn find_closing(text: &str, start: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut escaped = false;
+    for (index, c) in text[start..].char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '{' if !escaped => depth += 1,
+            '}' if !escaped => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(start + index);
+                }
+            }
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("\\{", "{").replace("\\}", "}")
+}
+
+/// A step of a chord chain: its keys, whether the mode it enters is locked,
+/// and when it fires, see `Binding`.
+struct Chord {
+    combo: Vec<KeyId>,
+    locks_mode: bool,
+    on_release: bool,
+    replay: bool,
+}
+
+/// `super + a ; b` -> [super+a, b], `None` if the hotkey can't be bound.
+fn parse_chain(
+    config: &mut Config,
+    hotkey: &str,
+    report: &mut impl FnMut(String),
+) -> Result<Option<Vec<Chord>>, String> {
+    let mut chain = vec![];
+    let mut chord_start = 0;
+    for (index, c) in hotkey.char_indices().chain(Some((hotkey.len(), ';'))) {
+        if c != ';' && c != ':' {
+            continue;
+        }
+        let chord = &hotkey[chord_start..index];
+        chord_start = index + 1;
+        let mut combo = vec![];
+        let mut on_release = false;
+        let mut replay = false;
+        for key in chord.split('+').map(str::trim) {
+            // `@` and `~` mark the whole chord, like in sxhkd, in any order
+            let mut key = key;
+            loop {
+                if let Some(rest) = key.strip_prefix('@') {
+                    on_release = true;
+                    key = rest;
+                } else if let Some(rest) = key.strip_prefix('~') {
+                    replay = true;
+                    key = rest;
+                } else {
+                    break;
+                }
+            }
+            if key.is_empty() {
+                return Err(format!("empty key name in {:?}", hotkey));
+            }
+            if key.starts_with("button") {
+                report(format!("{:?}: pointer buttons are not supported yet, ignored", hotkey));
+                return Ok(None);
+            }
+            if ["mod2", "mod3", "mod5", "any"].contains(&key.to_lowercase().as_str()) {
+                report(format!("{:?}: {} is not supported yet, ignored", hotkey, key));
+                return Ok(None);
+            }
+            let name = MODIFIERS
+                .iter()
+                .find(|(modifier, _)| modifier.eq_ignore_ascii_case(key))
+                .map_or(key, |(_, keysym_name)| keysym_name);
+            combo.push(config.key(name).map_err(|err| err.to_string())?);
+        }
+        combo.sort_unstable();
+        combo.dedup();
+        chain.push(Chord {
+            combo,
+            locks_mode: c == ':',
+            on_release,
+            replay,
+        });
+    }
+    Ok(Some(chain))
+}
+
+/// Adds the chain to the tree, the chains with the same beginning share their modes.
+fn insert(mode: &mut Mode, mut chain: Vec<Chord>, command: String) {
+    let first = chain.remove(0);
+    if chain.is_empty() {
+        mode.bindings.push(Binding {
+            combo: first.combo,
+            action: Action::Shell(command),
+            on_release: first.on_release,
+            replay: first.replay,
+        });
+        return;
+    }
+    let existing = mode.bindings.iter().position(|binding| {
+        binding.combo == first.combo && matches!(binding.action, Action::Mode(_))
+    });
+    let Chord {
+        combo,
+        locks_mode,
+        on_release,
+        replay,
+    } = first;
+    let index = existing.unwrap_or_else(|| {
+        mode.bindings.push(Binding {
+            combo,
+            action: Action::Mode(Mode::default()),
+            on_release,
+            replay,
+        });
+        mode.bindings.len() - 1
+    });
+    if let Action::Mode(sub_mode) = &mut mode.bindings[index].action {
+        sub_mode.locked |= locks_mode;
+        insert(sub_mode, chain, command);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Vec<String> {
+        let (config, _) = parse_sxhkd(text).unwrap();
+        config.describe(&config.root)
+    }
+
+    fn error(text: &str) -> String {
+        match parse_sxhkd(text) {
+            Ok(_) => panic!("{:?} is parsed", text),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn braces() {
+        assert_eq!(expand("a{b,c}d{e,f}").unwrap(), ["abde", "abdf", "acde", "acdf"]);
+        assert_eq!(expand("no braces").unwrap(), ["no braces"]);
+    }
+
+    #[test]
+    fn nested_braces() {
+        assert_eq!(expand("x{a,b{c,d}}").unwrap(), ["xa", "xbc", "xbd"]);
+        assert_eq!(expand("{{a,b},c}!").unwrap(), ["a!", "b!", "c!"]);
+    }
+
+    #[test]
+    fn escaped_braces() {
+        assert_eq!(
+            expand(r"awk '\{print $1\}' {a,b}").unwrap(),
+            ["awk '{print $1}' a", "awk '{print $1}' b"]
+        );
+        assert_eq!(expand(r"{\{,\}}").unwrap(), ["{", "}"]);
+    }
+
+    #[test]
+    fn unclosed_brace() {
+        assert!(expand("super + {a,b").is_err());
+        assert!(expand("{a,{b,c}").is_err());
+        assert_eq!(error("super + {a,b\n    echo"), "Syntax error: line 1: unclosed brace");
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(expand("{1-4}").unwrap(), ["1", "2", "3", "4"]);
+        assert_eq!(expand("{a-c,x}").unwrap(), ["a", "b", "c", "x"]);
+        // not a range, the item is kept as it is
+        assert_eq!(expand("{9-1}").unwrap(), ["9-1"]);
+    }
+
+    #[test]
+    fn placeholders() {
+        assert_eq!(
+            expand("super + {_,shift + }{1-2}").unwrap(),
+            ["super + 1", "super + 2", "super + shift + 1", "super + shift + 2"]
+        );
+        assert_eq!(
+            parse("super + {_,shift + }q\n    echo {one,two}"),
+            ["Super_L+q: echo one", "Shift_L+Super_L+q: echo two"]
+        );
+    }
+
+    #[test]
+    fn one_command_for_every_combo() {
+        assert_eq!(
+            parse("super + {a,b}\n    echo same"),
+            ["Super_L+a: echo same", "Super_L+b: echo same"]
+        );
+    }
+
+    #[test]
+    fn count_mismatch() {
+        assert_eq!(
+            error("# comment\nsuper + {a,b,c}\n    echo {1,2}"),
+            "Syntax error: line 2: 3 combos but 2 commands");
+    }
+
+    #[test]
+    fn chord_chains() {
+        assert_eq!(
+            parse("super + a ; b\n    pavucontrol\nsuper + a ; c ; d\n    urxvt"),
+            ["Super_L+a { b: pavucontrol, c { d: urxvt } }"]
+        );
+    }
+
+    #[test]
+    fn modal_chains() {
+        assert_eq!(
+            parse("super + o : {_,shift + }r\n    bspc node -R {90,-90}"),
+            ["Super_L+o locked { r: bspc node -R 90, Shift_L+r: bspc node -R -90 }"]
+        );
+    }
+
+    #[test]
+    fn empty_key_in_chain() {
+        assert!(error("super + a ; \n    echo").starts_with("Syntax error: line 1: empty key name"));
+    }
+
+    #[test]
+    fn release_and_replay() {
+        assert_eq!(
+            parse("super + @t\n    xdotool type hi\n~@Print\n    scrot\n@~a ; b\n    echo chain"),
+            ["@Super_L+t: xdotool type hi", "~@Print: scrot", "~@a { b: echo chain }"]
+        );
+        assert_eq!(parse("{_,@}x\n    echo {press,release}"), ["x: echo press", "@x: echo release"]);
+    }
+
+    #[test]
+    fn continued_lines() {
+        assert_eq!(
+            parse("super + \\\n    x\n    echo one \\\n        two"),
+            ["Super_L+x: echo one two"]
+        );
+    }
+
+    #[test]
+    fn diagnostics() {
+        let text = "    orphan\n\
+                    super + a\n\
+                    \n\
+                    button1\n    echo click\n\
+                    super + @b\n    echo release\n\
+                    mod5 + c\n    echo mod5\n\
+                    super + d";
+        let (config, diagnostics) = parse_sxhkd(text).unwrap();
+        let diagnostics: Vec<_> = diagnostics.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            diagnostics,
+            [
+                "line 1: command without a hotkey is ignored",
+                "line 2: hotkey without a command is ignored",
+                "line 4: \"button1\": pointer buttons are not supported yet, ignored",
+                "line 8: \"mod5 + c\": mod5 is not supported yet, ignored",
+                "line 10: hotkey without a command is ignored",
+            ]
+        );
+        assert_eq!(config.describe(&config.root), ["@Super_L+b: echo release"]);
+    }
+}
@@ -0,0 +1,206 @@
+use std::{fs, path::Path, time::Duration};
+
+use serde::Deserialize;
+
+use super::{Action, Binding, Config, ConfigError, Mode};
+
+/// An example:
+/// ```toml
+/// [[bind]]
+/// keys = "super_l + c"
+///
+///   [[bind.bind]]
+///   keys = "n"
+///   exec = ["urxvt", "-e", "nmtui"]
+///
+///   [[bind.bind]]
+///   keys = "k"
+///   # milliseconds until the mode is left
+///   timeout = 2000
+///
+///     [[bind.bind.bind]]
+///     keys = "u"
+///     run = "setxkbmap us"
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlFile {
+    #[serde(default)]
+    bind: Vec<TomlBinding>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlBinding {
+    keys: String,
+    run: Option<String>,
+    exec: Option<Vec<String>>,
+    timeout: Option<u64>,
+    #[serde(default)]
+    bind: Vec<TomlBinding>,
+}
+
+/// Reads and compiles a TOML config file.
+pub fn load_toml(path: &Path) -> Result<Config, ConfigError> {
+    let text = fs::read_to_string(path).map_err(|err| ConfigError::Io(path.into(), err))?;
+    parse_toml(&text)
+}
+
+pub fn parse_toml(text: &str) -> Result<Config, ConfigError> {
+    let file: TomlFile = toml::from_str(text).map_err(|err| ConfigError::Syntax(err.to_string()))?;
+    let mut config = Config::new();
+    config.root = compile_mode(&mut config, file.bind, None)?;
+    Ok(config)
+}
+
+fn compile_mode(
+    config: &mut Config,
+    bindings: Vec<TomlBinding>,
+    timeout: Option<Duration>,
+) -> Result<Mode, ConfigError> {
+    let bindings = bindings
+        .into_iter()
+        .map(|binding| compile_binding(config, binding))
+        .collect::<Result<_, _>>()?;
+    Ok(Mode {
+        bindings,
+        locked: false,
+        timeout,
+    })
+}
+
+fn compile_binding(config: &mut Config, binding: TomlBinding) -> Result<Binding, ConfigError> {
+    let TomlBinding {
+        keys,
+        run,
+        exec,
+        timeout,
+        bind,
+    } = binding;
+    let combo = config.combo(&keys)?;
+    let invalid = |details: &str| ConfigError::InvalidBinding {
+        keys: keys.clone(),
+        details: details.into(),
+    };
+    let action = match (run, exec, bind.is_empty()) {
+        (Some(command), None, true) => Action::Shell(command),
+        (None, Some(argv), true) if argv.is_empty() => return Err(invalid("exec is empty")),
+        (None, Some(argv), true) => Action::Exec(argv),
+        (None, None, false) => {
+            let timeout = timeout.map(Duration::from_millis);
+            Action::Mode(compile_mode(config, bind, timeout)?)
+        }
+        (None, None, true) => return Err(invalid("needs one of run, exec or bind")),
+        _ => return Err(invalid("only one of run, exec or bind is allowed")),
+    };
+    if timeout.is_some() && !matches!(action, Action::Mode(_)) {
+        return Err(invalid("timeout is allowed only with bind"));
+    }
+    Ok(Binding {
+        combo,
+        action,
+        on_release: false,
+        replay: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Vec<String> {
+        let config = parse_toml(text).unwrap();
+        config.describe(&config.root)
+    }
+
+    fn error(text: &str) -> String {
+        match parse_toml(text) {
+            Ok(_) => panic!("{:?} is parsed", text),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn nested_modes() {
+        let text = r#"
+[[bind]]
+keys = "super_l + c"
+
+  [[bind.bind]]
+  keys = "n"
+  exec = ["urxvt", "-e", "nmtui"]
+
+  [[bind.bind]]
+  keys = "k"
+  timeout = 2000
+
+    [[bind.bind.bind]]
+    keys = "u"
+    run = "setxkbmap us"
+
+[[bind]]
+keys = "XF86AudioMute"
+run = "pactl set-sink-mute @DEFAULT_SINK@ toggle"
+"#;
+        assert_eq!(
+            parse(text),
+            [
+                r#"c+super_l { n: ["urxvt", "-e", "nmtui"], k 2s { u: setxkbmap us } }"#,
+                "XF86AudioMute: pactl set-sink-mute @DEFAULT_SINK@ toggle",
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_file() {
+        assert!(parse("").is_empty());
+    }
+
+    #[test]
+    fn the_escape_key_is_known() {
+        let config = parse_toml("").unwrap();
+        assert_eq!(config.keys[config.escape].0, "Escape");
+    }
+
+    #[test]
+    fn shared_keys() {
+        let config = parse_toml("[[bind]]\nkeys = \"super + a\"\nrun = \"a\"\n[[bind]]\nkeys = \"super_l + b\"\nrun = \"b\"").unwrap();
+        // super is an alias of super_l, the key is registered once
+        assert_eq!(config.root.bindings[0].combo[0], config.root.bindings[1].combo[0]);
+    }
+
+    #[test]
+    fn invalid_bindings() {
+        assert_eq!(
+            error("[[bind]]\nkeys = \"a\""),
+            "Invalid binding \"a\": needs one of run, exec or bind"
+        );
+        assert_eq!(
+            error("[[bind]]\nkeys = \"a\"\nrun = \"x\"\nexec = [\"x\"]"),
+            "Invalid binding \"a\": only one of run, exec or bind is allowed"
+        );
+        assert_eq!(
+            error("[[bind]]\nkeys = \"a\"\nexec = []"),
+            "Invalid binding \"a\": exec is empty"
+        );
+        assert_eq!(
+            error("[[bind]]\nkeys = \"a\"\nrun = \"x\"\ntimeout = 100"),
+            "Invalid binding \"a\": timeout is allowed only with bind"
+        );
+    }
+
+    #[test]
+    fn bad_keys() {
+        assert_eq!(error("[[bind]]\nkeys = \"no_such_key\"\nrun = \"x\""), "Unknown key name \"no_such_key\"");
+        assert_eq!(
+            error("[[bind]]\nkeys = \"super + \"\nrun = \"x\""),
+            "Invalid binding \"super + \": empty key name"
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert!(error("[[bind]]\nkeys = \"a\"\nrun = \"x\"\ncommand = \"y\"").starts_with("Syntax error: "));
+        assert!(error("[[bind]\nkeys = \"a\"").starts_with("Syntax error: "));
+    }
+}
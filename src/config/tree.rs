@@ -0,0 +1,144 @@
+use std::{rc::Rc, time::Duration};
+
+use crate::{display::Keysym, process};
+
+use super::{keysym_from_name, ConfigError};
+
+/// Index of a key in `Config::keys`
+pub type KeyId = usize;
+
+/// The compiled bindings.
+///
+/// It has the same shape as the tree the `sequence!` macro expands to:
+/// every mode is a list of combos, each one either runs something or enters a sub-mode.
+pub struct Config {
+    /// Every key that is used by the bindings
+    pub keys: Vec<(String, Keysym)>,
+    /// Leaves the sub-modes
+    pub escape: KeyId,
+    pub root: Mode,
+}
+
+#[derive(Default)]
+pub struct Mode {
+    pub bindings: Vec<Binding>,
+    /// the mode is kept after running a command, only escape leaves it
+    pub locked: bool,
+    /// the mode is left when no binding is used for this long
+    pub timeout: Option<Duration>,
+}
+
+pub struct Binding {
+    /// keys that must be pressed together
+    pub combo: Vec<KeyId>,
+    pub action: Action,
+    /// fires on the release of a key of the combo, its press is hidden
+    pub on_release: bool,
+    /// the event is replayed to the focused window, not hidden
+    pub replay: bool,
+}
+
+pub enum Action {
+    /// command line for `sh -c`
+    Shell(String),
+    /// program and its arguments
+    Exec(Vec<String>),
+    /// a function of a script
+    Callback(Rc<dyn Fn()>),
+    Mode(Mode),
+}
+
+impl Config {
+    pub fn new() -> Self {
+        let mut config = Self {
+            keys: vec![],
+            escape: 0,
+            root: Mode::default(),
+        };
+        config.escape = config.key("Escape").expect("Escape is a known keysym");
+        config
+    }
+
+    /// Registers the key by its name, and gives back its id.
+    pub fn key(&mut self, name: &str) -> Result<KeyId, ConfigError> {
+        let keysym = keysym_from_name(name).ok_or_else(|| ConfigError::UnknownKey(name.into()))?;
+        if let Some(id) = self.keys.iter().position(|(_, known)| *known == keysym) {
+            return Ok(id);
+        }
+        self.keys.push((name.into(), keysym));
+        Ok(self.keys.len() - 1)
+    }
+
+    /// Registers the keys of a combo like `"super_l + c"`.
+    pub fn combo(&mut self, combo: &str) -> Result<Vec<KeyId>, ConfigError> {
+        let names: Vec<_> = combo.split('+').map(str::trim).collect();
+        if names.iter().any(|name| name.is_empty()) {
+            return Err(ConfigError::InvalidBinding {
+                keys: combo.into(),
+                details: "empty key name".into(),
+            });
+        }
+        names.into_iter().map(|name| self.key(name)).collect()
+    }
+
+    /// The mode reached by entering the sub-modes of the bindings at the given indices
+    pub fn mode(&self, path: &[usize]) -> &Mode {
+        path.iter().fold(&self.root, |mode, index| {
+            match &mode.bindings[*index].action {
+                Action::Mode(sub_mode) => sub_mode,
+                _ => unreachable!("only sub-modes can be entered"),
+            }
+        })
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Action {
+    /// Starts the command. Sub-modes are entered by the state machine instead.
+    pub fn run(&self) {
+        match self {
+            Action::Shell(command) => process::spawn_shell(command),
+            Action::Exec(argv) => process::spawn(argv),
+            Action::Callback(callback) => callback(),
+            Action::Mode(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+impl Config {
+    /// The bindings of a mode as `"combo: command"`, the sub-modes as `"combo { ... }"`,
+    /// the key names of a combo are sorted, a release binding starts with `@`, a replayed one with `~`.
+    pub fn describe(&self, mode: &Mode) -> Vec<String> {
+        mode.bindings
+            .iter()
+            .map(|binding| {
+                let mut names: Vec<_> = binding.combo.iter().map(|id| self.keys[*id].0.as_str()).collect();
+                names.sort_unstable();
+                let combo = format!(
+                    "{}{}{}",
+                    if binding.replay { "~" } else { "" },
+                    if binding.on_release { "@" } else { "" },
+                    names.join("+")
+                );
+                match &binding.action {
+                    Action::Shell(command) => format!("{}: {}", combo, command),
+                    Action::Exec(argv) => format!("{}: {:?}", combo, argv),
+                    Action::Callback(_) => format!("{}: <callback>", combo),
+                    Action::Mode(sub_mode) => format!(
+                        "{}{}{} {{ {} }}",
+                        combo,
+                        if sub_mode.locked { " locked" } else { "" },
+                        sub_mode.timeout.map_or(String::new(), |after| format!(" {:?}", after)),
+                        self.describe(sub_mode).join(", ")
+                    ),
+                }
+            })
+            .collect()
+    }
+}
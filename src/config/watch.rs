@@ -0,0 +1,86 @@
+use std::{
+    ffi::{CString, OsString},
+    fs, io,
+    mem::size_of,
+    os::unix::{
+        ffi::OsStrExt,
+        io::RawFd,
+    },
+    path::Path,
+};
+
+/// Notifies about the changes of a file through inotify.
+///
+/// The directory is watched instead of the file,
+/// because most editors save by replacing the file with a new one.
+pub struct FileWatcher {
+    fd: RawFd,
+    file_name: OsString,
+}
+
+impl FileWatcher {
+    pub fn new(path: &Path) -> io::Result<Self> {
+        // follow the symlinks of dotfile managers to the real file
+        let path = fs::canonicalize(path)?;
+        let (directory, file_name) = match (path.parent(), path.file_name()) {
+            (Some(directory), Some(file_name)) => (directory, file_name.to_owned()),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a file")),
+        };
+        let directory = CString::new(directory.as_os_str().as_bytes())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        unsafe {
+            let fd = libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC);
+            if fd == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO;
+            if libc::inotify_add_watch(fd, directory.as_ptr(), mask) == -1 {
+                let err = io::Error::last_os_error();
+                libc::close(fd);
+                return Err(err);
+            }
+            Ok(Self { fd, file_name })
+        }
+    }
+
+    /// Becomes readable when something has changed in the directory.
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// Reads the pending notifications, gives back whether the file was changed.
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        let mut buffer = [0u8; 4096];
+        loop {
+            let len = unsafe {
+                libc::read(self.fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len())
+            };
+            if len <= 0 {
+                return changed;
+            }
+            let mut offset = 0;
+            while offset + size_of::<libc::inotify_event>() <= len as usize {
+                let event: libc::inotify_event = unsafe {
+                    std::ptr::read_unaligned(buffer[offset..].as_ptr() as *const libc::inotify_event)
+                };
+                let name_start = offset + size_of::<libc::inotify_event>();
+                let name = &buffer[name_start..name_start + event.len as usize];
+                // the name is padded with zeros
+                let name = name.split(|byte| *byte == 0).next().unwrap_or_default();
+                if name == self.file_name.as_bytes() {
+                    changed = true;
+                }
+                offset = name_start + event.len as usize;
+            }
+        }
+    }
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use super::{Keycode, Button, Keysym};
+use std::os::unix::io::RawFd;
+
+use super::{Keycode, Button, Keysym, Modifiers, Pointer};
 
 /// Generic display server.
 ///
@@ -10,10 +12,32 @@ pub trait DisplayServerClient {
     /// get next event
     fn wait_for_event(&mut self) -> Option<DisplayServerEvent>;
 
+    /// get every event that has already arrived, without blocking
+    fn poll_for_events(&mut self) -> Vec<DisplayServerEvent>;
+
+    /// becomes readable when new events arrive
+    fn connection_fd(&self) -> RawFd;
+
+    /// becomes readable when the pointer moves, if it's tracked on another connection
+    fn pointer_fd(&self) -> Option<RawFd>;
+
+    /// send `PointerMotion` events, gives back whether it is supported
+    fn track_pointer(&mut self) -> bool;
+
+    /// false when the connection is broken, e.g. the server has exited
+    fn is_connected(&self) -> bool;
+
+    /// connect to the display server again, the grabs have to be made again
+    fn reconnect(&mut self) -> bool;
+
     /// let other clients get the event
     /// this must be called after every event
     fn release_event(&mut self, event: DisplayServerEvent, handling: EventHandling);
 
+    /// send a key event as if it came from the keyboard, like a press that was held back,
+    /// the press is not taken by the grabs of this client, it goes to the focused window
+    fn fake_key(&mut self, keycode: Keycode, press: bool);
+
     /// subscribe to key events
     fn grab_keysym_checked(
         &mut self,
@@ -21,13 +45,29 @@ pub trait DisplayServerClient {
         modifiers: u16,
     ) -> (Vec<Keycode>, Vec<GrabError<Keycode>>);
 
-    /// subscribe to key events
+    /// subscribe to key events,
+    /// the grabs ignore the lock modifiers: CapsLock, NumLock and ScrollLock
     fn grab_keycode_checked(
         &mut self,
         keysym: Keycode,
         modifiers: u16,
     ) -> Result<Keycode, GrabError<Keycode>>;
 
+    /// unsubscribe from key events
+    fn ungrab_keycode(&mut self, keycode: Keycode, modifiers: u16);
+
+    /// the keycodes that produce the keysym, without grabbing them
+    fn keycodes(&mut self, keysym: Keysym) -> Vec<Keycode>;
+
+    /// get every key event until `ungrab_keyboard`, gives back whether it succeeded
+    fn grab_keyboard(&mut self) -> bool;
+
+    /// end the grab of `grab_keyboard`
+    fn ungrab_keyboard(&mut self);
+
+    /// the keys that are held down at the moment
+    fn pressed_keycodes(&mut self) -> Vec<Keycode>;
+
     /// subscribe to pointer events
     fn grab_button_checked(
         &mut self,
@@ -35,16 +75,30 @@ pub trait DisplayServerClient {
         modifiers: u16,
     ) -> Result<(), GrabError<Button>>;
 
+    /// unsubscribe from pointer events
+    fn ungrab_button(&mut self, button: Button, modifiers: u16);
+
     /// apply changes
     fn flush(&mut self);
+
+    /// release every grab and let the frozen events go, before exiting
+    fn ungrab_all(&mut self);
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DisplayServerEvent {
-    KeyRelease(Keycode),
-    KeyPress(Keycode),
-    ButtonRelease(Button),
-    ButtonPress(Button),
+    KeyRelease(Keycode, Modifiers),
+    KeyPress(Keycode, Modifiers),
+    /// a held key pressed again by the autorepeat, see `KeyState::repeated`
+    KeyRepeat(Keycode, Modifiers),
+    ButtonRelease(Button, Modifiers),
+    ButtonPress(Button, Modifiers),
+    /// the pointer has moved, or pushed against the edge of the screen
+    PointerMotion(Pointer),
+    /// the pointer has not moved for a while, see `EventLoop::track_pointer`
+    PointerRest(Pointer),
+    /// the keyboard layout has changed, the keysyms could be on other keycodes
+    KeymapChange,
 }
 
 pub enum EventHandling {
@@ -2,7 +2,7 @@
 pub use x11::keysym::*;
 
 /// Modifier masks
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(u16)]
 pub enum Modifier {
     Mod1 = xcb::MOD_MASK_1 as u16,
@@ -15,23 +15,103 @@ pub enum Modifier {
     Lock = xcb::MOD_MASK_LOCK as u16,
     Shift = xcb::MOD_MASK_SHIFT as u16,
 }
+
+/// The usual modifier mapping
+const MODIFIER_KEYS: [(Keysym, Modifier); 12] = [
+    (XK_Shift_L, Modifier::Shift),
+    (XK_Shift_R, Modifier::Shift),
+    (XK_Control_L, Modifier::Control),
+    (XK_Control_R, Modifier::Control),
+    (XK_Alt_L, Modifier::Mod1),
+    (XK_Alt_R, Modifier::Mod1),
+    (XK_Meta_L, Modifier::Mod1),
+    (XK_Meta_R, Modifier::Mod1),
+    (XK_Super_L, Modifier::Mod4),
+    (XK_Super_R, Modifier::Mod4),
+    (XK_Hyper_L, Modifier::Mod4),
+    (XK_Hyper_R, Modifier::Mod4),
+];
+
+impl Modifier {
+    /// The modifier that the key sets, e.g. Mod4 for Super_L.
+    pub fn of_keysym(keysym: Keysym) -> Option<Self> {
+        MODIFIER_KEYS
+            .iter()
+            .find(|(modifier_keysym, _)| *modifier_keysym == keysym)
+            .map(|(_, modifier)| *modifier)
+    }
+
+    /// The mask of a grab, `Any` when no modifiers are given.
+    pub fn grab_mask(modifiers: &[Modifier]) -> u16 {
+        if modifiers.is_empty() {
+            return Modifier::Any as u16;
+        }
+        modifiers.iter().fold(0, |mask, modifier| mask | *modifier as u16)
+    }
+}
+
+/// The modifiers held down during an event
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct Modifiers {
-    mod1: bool,
-    mod2: bool,
-    mod3: bool,
-    mod4: bool,
-    mod5: bool,
-    control: bool,
-    lock: bool,
-    shift: bool,
+    pub mod1: bool,
+    pub mod2: bool,
+    pub mod3: bool,
+    pub mod4: bool,
+    pub mod5: bool,
+    pub control: bool,
+    pub lock: bool,
+    pub shift: bool,
+}
+
+impl Modifiers {
+    pub fn from_mask(mask: u16) -> Self {
+        let has = |modifier: Modifier| mask & modifier as u16 != 0;
+        Self {
+            mod1: has(Modifier::Mod1),
+            mod2: has(Modifier::Mod2),
+            mod3: has(Modifier::Mod3),
+            mod4: has(Modifier::Mod4),
+            mod5: has(Modifier::Mod5),
+            control: has(Modifier::Control),
+            lock: has(Modifier::Lock),
+            shift: has(Modifier::Shift),
+        }
+    }
+
+    pub fn mask(&self) -> u16 {
+        [
+            (self.mod1, Modifier::Mod1),
+            (self.mod2, Modifier::Mod2),
+            (self.mod3, Modifier::Mod3),
+            (self.mod4, Modifier::Mod4),
+            (self.mod5, Modifier::Mod5),
+            (self.control, Modifier::Control),
+            (self.lock, Modifier::Lock),
+            (self.shift, Modifier::Shift),
+        ]
+        .iter()
+        .filter(|(held, _)| *held)
+        .fold(0, |mask, (_, modifier)| mask | *modifier as u16)
+    }
 }
 
 pub type Keysym = xcb::Keysym;
 pub type Keycode = xcb::Keycode;
 pub type Button = xcb::Button;
 
+/// Mouse buttons, the scroll wheel sends button presses too
+pub const BUTTON_LEFT: Button = 1;
+pub const BUTTON_MIDDLE: Button = 2;
+pub const BUTTON_RIGHT: Button = 3;
+pub const SCROLL_UP: Button = 4;
+pub const SCROLL_DOWN: Button = 5;
+pub const SCROLL_LEFT: Button = 6;
+pub const SCROLL_RIGHT: Button = 7;
+
 mod client;
+mod pointer;
 mod x;
 
 pub use client::*;
+pub use pointer::*;
 pub use x::X11Client;
\ No newline at end of file
@@ -0,0 +1,86 @@
+/// A rectangle of the screen, read from RandR
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Monitor {
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Monitor {
+    pub fn contains(&self, x: i16, y: i16) -> bool {
+        let (x, y) = (i32::from(x), i32::from(y));
+        let (left, top) = (i32::from(self.x), i32::from(self.y));
+        x >= left && x < left + i32::from(self.width) && y >= top && y < top + i32::from(self.height)
+    }
+
+    fn right(&self) -> i32 {
+        i32::from(self.x) + i32::from(self.width) - 1
+    }
+
+    fn bottom(&self) -> i32 {
+        i32::from(self.y) + i32::from(self.height) - 1
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// The position of the pointer on the root window, and the monitor under it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Pointer {
+    pub x: i16,
+    pub y: i16,
+    pub monitor: Monitor,
+}
+
+impl Pointer {
+    /// The edges of the monitor that the pointer touches, at most two in a corner.
+    pub fn edges(&self) -> Vec<Edge> {
+        let (x, y) = (i32::from(self.x), i32::from(self.y));
+        let monitor = &self.monitor;
+        // the monitor is unknown before the first motion
+        if monitor.width == 0 || monitor.height == 0 {
+            return vec![];
+        }
+        [
+            (y == i32::from(monitor.y), Edge::Top),
+            (y == monitor.bottom(), Edge::Bottom),
+            (x == i32::from(monitor.x), Edge::Left),
+            (x == monitor.right(), Edge::Right),
+        ]
+        .iter()
+        .filter(|(touches, _)| *touches)
+        .map(|(_, edge)| *edge)
+        .collect()
+    }
+
+    pub fn edge(&self) -> Option<Edge> {
+        self.edges().first().copied()
+    }
+
+    pub fn corner(&self) -> Option<Corner> {
+        let edges = self.edges();
+        let touches = |edge| edges.contains(&edge);
+        match (touches(Edge::Top), touches(Edge::Bottom), touches(Edge::Left), touches(Edge::Right)) {
+            (true, _, true, _) => Some(Corner::TopLeft),
+            (true, _, _, true) => Some(Corner::TopRight),
+            (_, true, true, _) => Some(Corner::BottomLeft),
+            (_, true, _, true) => Some(Corner::BottomRight),
+            _ => None,
+        }
+    }
+}
@@ -1,13 +1,26 @@
+use std::{
+    collections::HashSet,
+    os::unix::io::{AsRawFd, RawFd},
+    time::{Duration, Instant},
+};
+
 use xcb::{
-    cast_event, ButtonPressEvent, ButtonReleaseEvent, KeyPressEvent, KeyReleaseEvent, ReplyError,
+    cast_event, randr, xkb, ButtonPressEvent, ButtonReleaseEvent, KeyPressEvent, KeyReleaseEvent,
+    MotionNotifyEvent, ReplyError,
 };
 use xcb_util::keysyms::KeySymbols;
 
 use crate::display::{
     client::{DisplayServerEvent as Event, GrabError},
-    Button, DisplayServerClient, EventHandling, Keycode, Keysym,
+    Button, DisplayServerClient, EventHandling, Keycode, Keysym, Modifier, Modifiers, Monitor,
+    Pointer, XK_Num_Lock, XK_Scroll_Lock,
 };
 
+use super::raw_motion::RawMotion;
+
+/// A faked press comes back through the grabs in this time, if it comes back at all.
+const FAKE_TIMEOUT: Duration = Duration::from_secs(1);
+
 // Definitions:
 // display: the collection of monitors that share common keyboards and pointers
 // screen: a single monitor with common keyboards and pointers
@@ -19,12 +32,34 @@ pub struct X11Client {
     conn: xcb::Connection,
     /// The most outer window in the window tree.
     root: xcb::Window,
+    /// CapsLock, NumLock and ScrollLock, the grabs are made with all of their combinations
+    lock_modifiers: u16,
+    /// the pointer motion, when it's tracked
+    raw_motion: Option<RawMotion>,
+    monitors: Vec<Monitor>,
+    /// the type of the RandR screen change events, when the extension is present
+    randr_screen_change: Option<u8>,
+    /// the type of the XKB events, when the extension is used
+    xkb_event: Option<u8>,
+    /// the keys pressed and not released, a press of them again is the autorepeat
+    held_keys: HashSet<Keycode>,
+    /// a replay ended a passive grab, the releases of the keys hidden before could have been missed
+    held_keys_stale: bool,
+    /// the whole keyboard is grabbed, every release is received
+    keyboard_grabbed: bool,
+    /// the presses sent by `fake_key`, they are replayed when a grab takes them
+    faked: Vec<(Keycode, Instant)>,
 }
 
 impl X11Client {
     pub fn new() -> Self {
-        let (conn, screen_idx) = xcb::Connection::connect(None).expect("Can't open display");
-        Self {
+        Self::connect().expect("Can't open display")
+    }
+
+    /// Connects to `$DISPLAY`.
+    fn connect() -> Result<Self, xcb::ConnError> {
+        let (conn, screen_idx) = xcb::Connection::connect(None)?;
+        let mut client = Self {
             root: conn
                 .get_setup()
                 .roots()
@@ -32,6 +67,179 @@ impl X11Client {
                 .expect("Can't acquire screen")
                 .root(),
             conn,
+            lock_modifiers: 0,
+            raw_motion: None,
+            monitors: vec![],
+            randr_screen_change: None,
+            xkb_event: None,
+            held_keys: HashSet::new(),
+            held_keys_stale: false,
+            keyboard_grabbed: false,
+            faked: vec![],
+        };
+        client.lock_modifiers = client.find_lock_modifiers();
+        client.enable_detectable_autorepeat();
+        client.select_keymap_events();
+        Ok(client)
+    }
+
+    /// A held key sends only presses, without the fake releases between them,
+    /// so the repeats can be told apart from the real presses.
+    fn enable_detectable_autorepeat(&self) {
+        let supported = xkb::use_extension(&self.conn, 1, 0)
+            .get_reply()
+            .map(|reply| reply.supported())
+            .unwrap_or(false);
+        let flag = xkb::PER_CLIENT_FLAG_DETECTABLE_AUTO_REPEAT;
+        let enabled = supported
+            && xkb::per_client_flags(&self.conn, xkb::ID_USE_CORE_KBD as u16, flag, flag, 0, 0, 0)
+                .get_reply()
+                .map(|reply| reply.value() & flag != 0)
+                .unwrap_or(false);
+        if !enabled {
+            println!("WARN: detectable autorepeat is not supported, a held key is released and pressed again");
+        }
+    }
+
+    /// The server doesn't send the core `MappingNotify` to the XKB clients
+    /// when the keyboard or its layout changes (setxkbmap), XKB reports it instead.
+    fn select_keymap_events(&mut self) {
+        let xkb = self.conn.get_extension_data(xkb::id());
+        self.xkb_event = xkb.filter(|xkb| xkb.present()).map(|xkb| xkb.first_event());
+        if self.xkb_event.is_none() {
+            return;
+        }
+        let events = (xkb::EVENT_TYPE_NEW_KEYBOARD_NOTIFY | xkb::EVENT_TYPE_MAP_NOTIFY) as u16;
+        let map_parts = (xkb::MAP_PART_KEY_TYPES | xkb::MAP_PART_KEY_SYMS | xkb::MAP_PART_MODIFIER_MAP) as u16;
+        xkb::select_events(
+            &self.conn,
+            xkb::ID_USE_CORE_KBD as xkb::DeviceSpec,
+            events,
+            0,
+            events,
+            map_parts,
+            map_parts,
+            None,
+        );
+    }
+
+    /// Whether the press is a fake of `fake_key`.
+    fn take_faked(&mut self, keycode: Keycode) -> bool {
+        let now = Instant::now();
+        self.faked.retain(|(_, at)| now.duration_since(*at) < FAKE_TIMEOUT);
+        match self.faked.iter().position(|(faked, _)| *faked == keycode) {
+            Some(index) => {
+                self.faked.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The releases are not seen without a grab,
+    /// so the held keys are read again when the keyboard is grabbed or ungrabbed.
+    fn resync_held_keys(&mut self) {
+        self.held_keys = self.pressed_keycodes().into_iter().collect();
+        self.held_keys_stale = false;
+    }
+
+    /// The monitors are the active CRTCs,
+    /// without RandR the whole screen is one monitor.
+    fn find_monitors(&self) -> Vec<Monitor> {
+        let monitors: Vec<_> = randr::get_screen_resources_current(&self.conn, self.root)
+            .get_reply()
+            .map(|resources| {
+                resources
+                    .crtcs()
+                    .iter()
+                    .map(|crtc| randr::get_crtc_info(&self.conn, *crtc, resources.config_timestamp()))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|cookie| cookie.get_reply().ok())
+            // the disabled ones have no size
+            .filter(|crtc| crtc.width() != 0 && crtc.height() != 0)
+            .map(|crtc| Monitor {
+                x: crtc.x(),
+                y: crtc.y(),
+                width: crtc.width(),
+                height: crtc.height(),
+            })
+            .collect();
+        if !monitors.is_empty() {
+            return monitors;
+        }
+        println!("WARN: could not get the monitors from RandR, using the whole screen");
+        let geometry = xcb::xproto::get_geometry(&self.conn, self.root).get_reply();
+        geometry
+            .map(|geometry| {
+                vec![Monitor {
+                    x: 0,
+                    y: 0,
+                    width: geometry.width(),
+                    height: geometry.height(),
+                }]
+            })
+            .unwrap_or_default()
+    }
+
+    /// Asks the pointer position.
+    fn query_pointer(&self) -> Option<Pointer> {
+        let reply = xcb::xproto::query_pointer(&self.conn, self.root).get_reply().ok()?;
+        Some(self.pointer_at(reply.root_x(), reply.root_y()))
+    }
+
+    /// Finds the monitor under the position.
+    fn pointer_at(&self, x: i16, y: i16) -> Pointer {
+        let monitor = self
+            .monitors
+            .iter()
+            .find(|monitor| monitor.contains(x, y))
+            .copied()
+            .unwrap_or_default();
+        Pointer { x, y, monitor }
+    }
+
+    /// NumLock and ScrollLock could be on any modifier, they are looked up in the modifier mapping.
+    fn find_lock_modifiers(&self) -> u16 {
+        let mut mask = Modifier::Lock as u16;
+        let reply = match xcb::xproto::get_modifier_mapping(&self.conn).get_reply() {
+            Ok(reply) => reply,
+            Err(_) => {
+                println!("WARN: could not get the modifier mapping, NumLock is expected on Mod2");
+                return mask | Modifier::Mod2 as u16;
+            }
+        };
+        let key_symbols = self.key_symbol_tool();
+        let per_modifier = reply.keycodes_per_modifier() as usize;
+        // the keycodes of Shift, Lock, Control, Mod1, ..., Mod5 in order
+        for (index, keycodes) in reply.keycodes().chunks(per_modifier.max(1)).enumerate() {
+            let is_lock = keycodes.iter().any(|keycode| {
+                let keysym = key_symbols.get_keysym(*keycode, 0);
+                *keycode != 0 && (keysym == XK_Num_Lock || keysym == XK_Scroll_Lock)
+            });
+            if is_lock {
+                mask |= 1 << index;
+            }
+        }
+        mask
+    }
+
+    /// The modifiers extended with every combination of the lock modifiers.
+    fn lock_variants(&self, modifiers: u16) -> Vec<u16> {
+        if modifiers == Modifier::Any as u16 {
+            return vec![modifiers];
+        }
+        let locks = self.lock_modifiers & !modifiers;
+        let mut variants = vec![];
+        let mut subset = locks;
+        loop {
+            variants.push(modifiers | subset);
+            if subset == 0 {
+                return variants;
+            }
+            subset = (subset - 1) & locks;
         }
     }
 
@@ -39,57 +247,186 @@ impl X11Client {
         KeySymbols::new(&self.conn)
     }
 
+    fn translate_event(&mut self, evt: xcb::GenericEvent) -> Option<Event> {
+        let event_type: u8 = evt.response_type();
+        match event_type {
+            xcb::KEY_PRESS => {
+                let event: &KeyPressEvent = unsafe { cast_event(&evt) };
+                let modifiers = Modifiers::from_mask(event.state());
+                if self.take_faked(event.detail()) {
+                    self.release_event(Event::KeyPress(event.detail(), modifiers), EventHandling::Replay);
+                    return None;
+                }
+                // only the missed releases are dropped, the queued presses are not seen by the query yet
+                if self.held_keys_stale {
+                    let pressed = self.pressed_keycodes();
+                    self.held_keys.retain(|keycode| pressed.contains(keycode));
+                    self.held_keys_stale = false;
+                }
+                // with detectable autorepeat, a held key is pressed again without a release
+                if self.held_keys.insert(event.detail()) {
+                    Some(Event::KeyPress(event.detail(), modifiers))
+                } else {
+                    Some(Event::KeyRepeat(event.detail(), modifiers))
+                }
+            }
+            xcb::KEY_RELEASE => {
+                let event: &KeyReleaseEvent = unsafe { cast_event(&evt) };
+                self.held_keys.remove(&event.detail());
+                Some(Event::KeyRelease(event.detail(), Modifiers::from_mask(event.state())))
+            }
+            xcb::BUTTON_PRESS => {
+                let event: &ButtonPressEvent = unsafe { cast_event(&evt) };
+                Some(Event::ButtonPress(event.detail(), Modifiers::from_mask(event.state())))
+            }
+            xcb::BUTTON_RELEASE => {
+                let event: &ButtonReleaseEvent = unsafe { cast_event(&evt) };
+                Some(Event::ButtonRelease(event.detail(), Modifiers::from_mask(event.state())))
+            }
+            // reported by the button grabs while a button is held
+            xcb::MOTION_NOTIFY => {
+                let event: &MotionNotifyEvent = unsafe { cast_event(&evt) };
+                Some(Event::PointerMotion(self.pointer_at(event.root_x(), event.root_y())))
+            }
+            // a monitor is plugged in or the resolution is changed
+            e if Some(e) == self.randr_screen_change => {
+                self.monitors = self.find_monitors();
+                None
+            }
+            // when the user changes keyboard layout
+            xcb::MAPPING_NOTIFY => self.handle_keymap_change(evt),
+            e if Some(e) == self.xkb_event => {
+                // every XKB event has its subtype at the same place
+                let event: &xkb::NewKeyboardNotifyEvent = unsafe { cast_event(&evt) };
+                match event.xkb_type() {
+                    xkb::NEW_KEYBOARD_NOTIFY | xkb::MAP_NOTIFY => {
+                        // NumLock could have moved too
+                        self.lock_modifiers = self.find_lock_modifiers();
+                        Some(Event::KeymapChange)
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
     // For e.g: US layout to HU layout
-    pub fn handle_keymap_change(&mut self, evt: xcb::GenericEvent) {
+    pub fn handle_keymap_change(&mut self, evt: xcb::GenericEvent) -> Option<Event> {
         let e: &xcb::MappingNotifyEvent = unsafe { cast_event(&evt) };
-        if self.key_symbol_tool().refresh_keyboard_mapping(e) == 1 {
-            println!("mapping notify {:?} {:?}", e.request(), e.count());
+        self.key_symbol_tool().refresh_keyboard_mapping(e);
+        match e.request() as u32 {
+            xcb::MAPPING_KEYBOARD => Some(Event::KeymapChange),
+            // NumLock could have moved, the lock variants of the grabs change
+            xcb::MAPPING_MODIFIER => {
+                self.lock_modifiers = self.find_lock_modifiers();
+                Some(Event::KeymapChange)
+            }
+            _ => None,
         }
     }
 }
 
-impl<'a> DisplayServerClient for X11Client {
+impl Default for X11Client {
+    /// Connects to `$DISPLAY`, panics if it can't.
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DisplayServerClient for X11Client {
     fn wait_for_event(&mut self) -> Option<Event> {
-        self.conn.wait_for_event().and_then(|evt| {
-            let event_type: u8 = evt.response_type();
-            match event_type {
-                xcb::KEY_PRESS => {
-                    let event: &KeyPressEvent = unsafe { cast_event(&evt) };
-                    Some(Event::KeyPress(event.detail()))
-                }
-                xcb::KEY_RELEASE => {
-                    let event: &KeyReleaseEvent = unsafe { cast_event(&evt) };
-                    Some(Event::KeyRelease(event.detail()))
-                }
-                xcb::BUTTON_PRESS => {
-                    let event: &ButtonPressEvent = unsafe { cast_event(&evt) };
-                    Some(Event::ButtonPress(event.detail()))
-                }
-                xcb::BUTTON_RELEASE => {
-                    let event: &ButtonReleaseEvent = unsafe { cast_event(&evt) };
-                    Some(Event::ButtonRelease(event.detail()))
-                }
-                // when the user changes keyboard layout
-                xcb::MAPPING_NOTIFY => {
-                    println!("Mapping");
-                    self.handle_keymap_change(evt);
-                    None
-                }
-                e => {
-                    println!("received event, {:?}", e);
-                    None
+        self.conn
+            .wait_for_event()
+            .and_then(|evt| self.translate_event(evt))
+    }
+
+    fn poll_for_events(&mut self) -> Vec<Event> {
+        let mut events = vec![];
+        while let Some(evt) = self.conn.poll_for_event() {
+            match self.translate_event(evt) {
+                // setxkbmap sends a burst of them, one regrab is enough
+                Some(Event::KeymapChange) if events.contains(&Event::KeymapChange) => {}
+                event => events.extend(event),
+            }
+        }
+        // Xlib would exit on the broken connection
+        if let (Some(raw_motion), true) = (&mut self.raw_motion, self.conn.has_error().is_ok()) {
+            // only the last position matters
+            if raw_motion.moved() {
+                events.extend(self.query_pointer().map(Event::PointerMotion));
+            }
+        }
+        events
+    }
+
+    fn connection_fd(&self) -> RawFd {
+        self.conn.as_raw_fd()
+    }
+
+    fn pointer_fd(&self) -> Option<RawFd> {
+        self.raw_motion.as_ref().map(RawMotion::fd)
+    }
+
+    fn track_pointer(&mut self) -> bool {
+        if self.raw_motion.is_some() {
+            return true;
+        }
+        let randr = self.conn.get_extension_data(randr::id());
+        self.randr_screen_change = randr
+            .filter(|randr| randr.present())
+            .map(|randr| randr.first_event() + randr::SCREEN_CHANGE_NOTIFY);
+        if self.randr_screen_change.is_some() {
+            randr::select_input(&self.conn, self.root, randr::NOTIFY_MASK_SCREEN_CHANGE as u16);
+        }
+        self.monitors = self.find_monitors();
+        self.raw_motion = RawMotion::new();
+        self.conn.flush();
+        self.raw_motion.is_some()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.conn.has_error().is_ok()
+    }
+
+    fn reconnect(&mut self) -> bool {
+        match Self::connect() {
+            Ok(client) => {
+                let tracking = self.raw_motion.is_some();
+                // the old connection is closed when it's dropped
+                *self = client;
+                if tracking {
+                    self.track_pointer();
                 }
+                true
             }
-        })
+            Err(err) => {
+                println!("WARN: could not connect to the X server: {:?}", err);
+                false
+            }
+        }
     }
 
     fn release_event(&mut self, event: Event, handling: EventHandling) {
         use EventHandling::{Hide, Replay};
+        // the replay ends the passive grab, the releases go to the focused window
+        match (event, &handling, self.keyboard_grabbed) {
+            (Event::KeyPress(keycode, _) | Event::KeyRepeat(keycode, _), Replay, false) => {
+                self.held_keys.remove(&keycode);
+                self.held_keys_stale = true;
+            }
+            (Event::KeyRelease(..), Replay, false) => self.held_keys_stale = true,
+            _ => {}
+        }
         let mode = match (event, handling) {
-            (Event::KeyPress(_) | Event::KeyRelease(_), Replay) => xcb::ALLOW_REPLAY_KEYBOARD,
-            (Event::KeyPress(_) | Event::KeyRelease(_), Hide) => xcb::ALLOW_SYNC_KEYBOARD,
-            (Event::ButtonPress(_) | Event::ButtonRelease(_), Replay) => xcb::ALLOW_REPLAY_POINTER,
-            (Event::ButtonPress(_) | Event::ButtonRelease(_), Hide) => xcb::ALLOW_SYNC_POINTER,
+            (Event::KeyPress(..) | Event::KeyRepeat(..) | Event::KeyRelease(..), Replay) => {
+                xcb::ALLOW_REPLAY_KEYBOARD
+            }
+            (Event::KeyPress(..) | Event::KeyRepeat(..) | Event::KeyRelease(..), Hide) => {
+                xcb::ALLOW_SYNC_KEYBOARD
+            }
+            (Event::ButtonPress(..) | Event::ButtonRelease(..), Replay) => xcb::ALLOW_REPLAY_POINTER,
+            (Event::ButtonPress(..) | Event::ButtonRelease(..), Hide) => xcb::ALLOW_SYNC_POINTER,
             _ => {
                 // only keyboard and pointer events could be allowed
                 return;
@@ -99,32 +436,98 @@ impl<'a> DisplayServerClient for X11Client {
         self.conn.flush();
     }
 
+    fn fake_key(&mut self, keycode: Keycode, press: bool) {
+        let event_type = if press { xcb::KEY_PRESS } else { xcb::KEY_RELEASE };
+        if press {
+            self.faked.push((keycode, Instant::now()));
+        }
+        xcb::test::fake_input(&self.conn, event_type, keycode, xcb::CURRENT_TIME, self.root, 0, 0, 0);
+        self.conn.flush();
+    }
+
     /// subscribe to key events
     fn grab_keycode_checked(
         &mut self,
         keycode: xcb::Keycode,
         modifiers: u16,
     ) -> Result<xcb::Keycode, GrabError<xcb::Keycode>> {
-        xcb::xproto::grab_key(
+        self.lock_variants(modifiers)
+            .into_iter()
+            .try_for_each(|modifiers| {
+                xcb::xproto::grab_key(
+                    &self.conn,
+                    true,
+                    self.root,
+                    modifiers,
+                    keycode,
+                    xcb::GRAB_MODE_ASYNC as u8,
+                    xcb::GRAB_MODE_SYNC as u8,
+                )
+                .request_check()
+                .map_err(|reply_error| GrabError {
+                    details: fetch_error(reply_error),
+                    event_type: "key",
+                    value: keycode,
+                    modifiers,
+                })
+            })
+            .map(|()| keycode)
+    }
+
+    fn ungrab_keycode(&mut self, keycode: Keycode, modifiers: u16) {
+        for modifiers in self.lock_variants(modifiers) {
+            xcb::xproto::ungrab_key(&self.conn, keycode, self.root, modifiers);
+        }
+    }
+
+    fn keycodes(&mut self, keysym: Keysym) -> Vec<Keycode> {
+        self.key_symbol_tool().get_keycode(keysym).collect()
+    }
+
+    fn grab_keyboard(&mut self) -> bool {
+        // asynchronous, the events don't have to be allowed one by one
+        let status = xcb::xproto::grab_keyboard(
             &self.conn,
-            true,
+            false,
             self.root,
-            modifiers,
-            keycode,
+            xcb::CURRENT_TIME,
+            xcb::GRAB_MODE_ASYNC as u8,
             xcb::GRAB_MODE_ASYNC as u8,
-            xcb::GRAB_MODE_SYNC as u8,
         )
-        .request_check()
-        .map(|_| {
-            println!("grab key {:?} {:?} ", keycode, modifiers);
-            keycode
-        })
-        .map_err(|reply_error| GrabError {
-            details: fetch_error(reply_error),
-            event_type: "key",
-            value: keycode,
-            modifiers: modifiers,
-        })
+        .get_reply()
+        .map(|reply| reply.status());
+        match status {
+            Ok(status) if status == xcb::GRAB_STATUS_SUCCESS as u8 => {
+                self.keyboard_grabbed = true;
+                self.resync_held_keys();
+                true
+            }
+            Ok(status) => {
+                println!("WARN: could not grab the keyboard, status {}", status);
+                false
+            }
+            Err(_) => {
+                println!("WARN: could not grab the keyboard");
+                false
+            }
+        }
+    }
+
+    fn ungrab_keyboard(&mut self) {
+        xcb::xproto::ungrab_keyboard(&self.conn, xcb::CURRENT_TIME);
+        self.conn.flush();
+        self.keyboard_grabbed = false;
+        self.resync_held_keys();
+    }
+
+    fn pressed_keycodes(&mut self) -> Vec<Keycode> {
+        match xcb::xproto::query_keymap(&self.conn).get_reply() {
+            // a bit for every keycode
+            Ok(reply) => (0..=255u8)
+                .filter(|keycode| reply.keys()[*keycode as usize / 8] & (1 << (keycode % 8)) != 0)
+                .collect(),
+            Err(_) => vec![],
+        }
     }
 
     fn grab_keysym_checked(
@@ -132,7 +535,7 @@ impl<'a> DisplayServerClient for X11Client {
         keysym: Keysym,
         modifiers: u16,
     ) -> (Vec<Keycode>, Vec<GrabError<Keycode>>) {
-        let keycodes: Vec<_> = self.key_symbol_tool().get_keycode(keysym).collect();
+        let keycodes = self.keycodes(keysym);
         let (oks, errs): (Vec<_>, Vec<_>) = keycodes
             .iter()
             .map(|keycode| self.grab_keycode_checked(*keycode, modifiers))
@@ -148,38 +551,47 @@ impl<'a> DisplayServerClient for X11Client {
         button: Button,
         modifiers: u16,
     ) -> Result<(), GrabError<Button>> {
-        xcb::xproto::grab_button(
-            &self.conn,
-            true,
-            self.root,
-            xcb::EVENT_MASK_BUTTON_PRESS as u16 | xcb::EVENT_MASK_BUTTON_RELEASE as u16,
-            xcb::GRAB_MODE_SYNC as u8,
-            xcb::GRAB_MODE_ASYNC as u8,
-            0,
-            0,
-            button,
-            modifiers,
-        )
-        .request_check()
-        .map(|_| {
-            println!("grab button {:?} {:?} ", button, modifiers);
-        })
-        .map_err(|reply_error| GrabError {
-            details: fetch_error(reply_error),
-            event_type: "button",
-            value: button,
-            modifiers: modifiers,
+        self.lock_variants(modifiers).into_iter().try_for_each(|modifiers| {
+            xcb::xproto::grab_button(
+                &self.conn,
+                true,
+                self.root,
+                // the motion is needed for the gestures
+                (xcb::EVENT_MASK_BUTTON_PRESS
+                    | xcb::EVENT_MASK_BUTTON_RELEASE
+                    | xcb::EVENT_MASK_BUTTON_MOTION) as u16,
+                xcb::GRAB_MODE_SYNC as u8,
+                xcb::GRAB_MODE_ASYNC as u8,
+                0,
+                0,
+                button,
+                modifiers,
+            )
+            .request_check()
+            .map_err(|reply_error| GrabError {
+                details: fetch_error(reply_error),
+                event_type: "button",
+                value: button,
+                modifiers,
+            })
         })
     }
 
+    fn ungrab_button(&mut self, button: Button, modifiers: u16) {
+        for modifiers in self.lock_variants(modifiers) {
+            xcb::xproto::ungrab_button(&self.conn, button, self.root, modifiers);
+        }
+    }
+
     fn flush(&mut self) {
         self.conn.flush();
     }
-}
 
-impl Drop for X11Client {
-    fn drop(&mut self) {
-        println!("UNGRAB");
+    fn ungrab_all(&mut self) {
+        // a synchronous grab could have frozen the devices
+        xcb::allow_events(&self.conn, xcb::ALLOW_ASYNC_BOTH as u8, xcb::CURRENT_TIME);
+        xcb::xproto::ungrab_keyboard(&self.conn, xcb::CURRENT_TIME);
+        xcb::xproto::ungrab_pointer(&self.conn, xcb::CURRENT_TIME);
         xcb::xproto::ungrab_key(
             &self.conn,
             xcb::GRAB_ANY as u8,
@@ -196,6 +608,15 @@ impl Drop for X11Client {
     }
 }
 
+impl Drop for X11Client {
+    fn drop(&mut self) {
+        self.ungrab_all();
+        if let Some(raw_motion) = self.raw_motion.take() {
+            raw_motion.close(self.is_connected());
+        }
+    }
+}
+
 fn fetch_error(reply_error: ReplyError) -> String {
     match reply_error {
         xcb::ReplyError::GenericError(ref err) if err.error_code() == xcb::ACCESS => {
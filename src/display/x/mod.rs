@@ -1,3 +1,4 @@
 mod client;
+mod raw_motion;
 
-pub use client::X11Client;
\ No newline at end of file
+pub use client::X11Client;
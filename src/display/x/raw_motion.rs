@@ -0,0 +1,101 @@
+use std::{ffi::CString, os::unix::io::RawFd, ptr};
+
+use x11::{xinput2, xlib};
+
+/// The XInput2 raw motion events of the master pointers.
+///
+/// Unlike the motion events of the root window, they arrive above any window,
+/// and even when the pointer is pushed against the edge of the screen.
+/// xcb has no XInput support, so they are received on an Xlib connection.
+pub struct RawMotion {
+    display: *mut xlib::Display,
+    /// the major opcode of XInputExtension, the events are marked by it
+    opcode: i32,
+}
+
+impl RawMotion {
+    pub fn new() -> Option<Self> {
+        let display = unsafe { xlib::XOpenDisplay(ptr::null()) };
+        if display.is_null() {
+            println!("WARN: could not open the display for the pointer motion");
+            return None;
+        }
+        let mut raw_motion = Self { display, opcode: 0 };
+        raw_motion.opcode = raw_motion.query_xinput2()?;
+        let mut mask = [0u8; 3];
+        xinput2::XISetMask(&mut mask, xinput2::XI_RawMotion);
+        let mut event_mask = xinput2::XIEventMask {
+            deviceid: xinput2::XIAllMasterDevices,
+            mask_len: mask.len() as i32,
+            mask: mask.as_mut_ptr(),
+        };
+        unsafe {
+            let root = xlib::XDefaultRootWindow(display);
+            xinput2::XISelectEvents(display, root, &mut event_mask, 1);
+            xlib::XFlush(display);
+        }
+        Some(raw_motion)
+    }
+
+    /// The opcode of the extension, if the server supports XInput 2.0.
+    fn query_xinput2(&self) -> Option<i32> {
+        let name = CString::new("XInputExtension").unwrap();
+        let (mut opcode, mut event, mut error) = (0, 0, 0);
+        let (mut major, mut minor) = (2, 0);
+        unsafe {
+            if xlib::XQueryExtension(self.display, name.as_ptr(), &mut opcode, &mut event, &mut error) == 0
+                || xinput2::XIQueryVersion(self.display, &mut major, &mut minor) != xlib::Success as i32
+            {
+                println!("WARN: XInput 2 is not supported, the pointer is not tracked");
+                return None;
+            }
+        }
+        Some(opcode)
+    }
+
+    pub fn fd(&self) -> RawFd {
+        unsafe { xlib::XConnectionNumber(self.display) }
+    }
+
+    /// Reads the arrived events without blocking, gives back whether the pointer has moved.
+    ///
+    /// Xlib exits on a broken connection, so this must not be called after the X server is gone.
+    pub fn moved(&mut self) -> bool {
+        let mut moved = false;
+        unsafe {
+            while xlib::XPending(self.display) > 0 {
+                let mut event: xlib::XEvent = std::mem::zeroed();
+                xlib::XNextEvent(self.display, &mut event);
+                let cookie = event.generic_event_cookie;
+                if cookie.type_ == xlib::GenericEvent
+                    && cookie.extension == self.opcode
+                    && cookie.evtype == xinput2::XI_RawMotion
+                {
+                    moved = true;
+                }
+            }
+        }
+        moved
+    }
+
+    /// Closes the connection, or only its socket if the X server is gone,
+    /// since Xlib would exit while closing a broken connection.
+    pub fn close(self, connected: bool) {
+        unsafe {
+            if connected {
+                xlib::XCloseDisplay(self.display);
+            } else {
+                libc::close(self.fd());
+            }
+        }
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for RawMotion {
+    fn drop(&mut self) {
+        unsafe {
+            xlib::XCloseDisplay(self.display);
+        }
+    }
+}
@@ -0,0 +1,388 @@
+//! The main loop, it waits on the display server connection, the timeout of the actual node,
+//! the signals and the registered event sources at the same time.
+//!
+//! SIGINT, SIGTERM and SIGQUIT stop the loop, the grabs are released before exiting.
+//! When the connection to the display server breaks, it reconnects and grabs the keys again.
+//! The pointer motion is sent to the state machine only after `EventLoop::track_pointer`.
+
+mod signal;
+mod timer;
+
+pub use signal::*;
+pub use timer::*;
+
+use std::{io, os::unix::io::RawFd, time::Duration, time::Instant};
+
+use crate::{
+    display::{DisplayServerClient, DisplayServerEvent, Pointer},
+    process,
+    state_machine::{Executor, ManageKeyState},
+};
+
+/// Called when an event source becomes readable.
+pub type Callback<C, K, U> = Box<dyn FnMut(&mut Executor<K, U>, &mut C)>;
+
+/// Identifies a registered event source, see `EventLoop::remove_source`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourceId(usize);
+
+struct Source<C, K, U> {
+    fd: RawFd,
+    callback: Callback<C, K, U>,
+    /// the timer is closed together with the source
+    _timer: Option<Timer>,
+}
+
+// epoll tokens of the built-in sources, the others are indexes of `sources`
+const DISPLAY: u64 = u64::MAX;
+const DEADLINE: u64 = u64::MAX - 1;
+const SHUTDOWN: u64 = u64::MAX - 2;
+const DWELL: u64 = u64::MAX - 3;
+
+const SHUTDOWN_SIGNALS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGQUIT];
+
+const MAX_EVENTS: usize = 16;
+
+const FIRST_RECONNECT_DELAY: Duration = Duration::from_millis(500);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Drives the `Executor` by the events of the display server,
+/// and calls the callbacks of the other event sources.
+///
+/// The finished children are reaped on SIGCHLD.
+pub struct EventLoop<C, K, U> {
+    client: C,
+    /// the watched connection fd, it changes when the client reconnects
+    display_fd: RawFd,
+    executor: Executor<K, U>,
+    epoll: RawFd,
+    /// expires at the deadline of the executor
+    deadline: Timer,
+    sources: Vec<Option<Source<C, K, U>>>,
+    /// the watched pointer motion fd, if the client tracks the pointer on another connection
+    pointer_fd: Option<RawFd>,
+    /// expires when the pointer has rested for the dwell time, see `track_pointer`
+    dwell: Option<(Duration, Timer)>,
+    /// the last position, until the pointer rests there
+    moved_to: Option<Pointer>,
+}
+
+impl<C: DisplayServerClient, K: ManageKeyState, U> EventLoop<C, K, U> {
+    pub fn new(client: C, executor: Executor<K, U>) -> Self {
+        let epoll = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if epoll == -1 {
+            panic!("Can't create epoll: {}", io::Error::last_os_error());
+        }
+        let deadline = Timer::new().unwrap_or_else(|err| panic!("Can't create timer: {}", err));
+        let mut event_loop = Self {
+            display_fd: client.connection_fd(),
+            client,
+            executor,
+            epoll,
+            deadline,
+            sources: vec![],
+            pointer_fd: None,
+            dwell: None,
+            moved_to: None,
+        };
+        let display_fd = event_loop.display_fd;
+        let deadline_fd = event_loop.deadline.fd();
+        event_loop
+            .watch(display_fd, DISPLAY)
+            .and_then(|()| event_loop.watch(deadline_fd, DEADLINE))
+            .unwrap_or_else(|err| panic!("Can't watch the display connection: {}", err));
+        for signal in SHUTDOWN_SIGNALS {
+            event_loop
+                .watch(signal_pipe(signal), SHUTDOWN)
+                .unwrap_or_else(|err| panic!("Can't watch signal {}: {}", signal, err));
+        }
+        event_loop
+            .add_signal(libc::SIGCHLD, |_, _| process::reap_children())
+            .unwrap_or_else(|err| panic!("Can't watch the children: {}", err));
+        event_loop
+    }
+
+    /// Calls the callback whenever the file descriptor is readable.
+    ///
+    /// The callback has to read everything, otherwise it is called again immediately.
+    /// The file descriptor is not closed by the loop.
+    pub fn add_source(
+        &mut self,
+        fd: RawFd,
+        callback: impl FnMut(&mut Executor<K, U>, &mut C) + 'static,
+    ) -> io::Result<SourceId> {
+        self.insert(fd, Box::new(callback), None)
+    }
+
+    /// Calls the callback after `after`, then in every `interval` if it's given.
+    pub fn add_timer(
+        &mut self,
+        after: Duration,
+        interval: Option<Duration>,
+        mut callback: impl FnMut(&mut Executor<K, U>, &mut C) + 'static,
+    ) -> io::Result<SourceId> {
+        let timer = Timer::new()?;
+        timer.set(Some(after), interval);
+        let fd = timer.fd();
+        let callback = move |executor: &mut Executor<K, U>, client: &mut C| {
+            drain(fd);
+            callback(executor, client)
+        };
+        self.insert(fd, Box::new(callback), Some(timer))
+    }
+
+    /// Calls the callback when the signal arrives, see `signal_pipe`.
+    pub fn add_signal(
+        &mut self,
+        signal: libc::c_int,
+        mut callback: impl FnMut(&mut Executor<K, U>, &mut C) + 'static,
+    ) -> io::Result<SourceId> {
+        let fd = signal_pipe(signal);
+        let callback = move |executor: &mut Executor<K, U>, client: &mut C| {
+            drain(fd);
+            callback(executor, client)
+        };
+        self.insert(fd, Box::new(callback), None)
+    }
+
+    /// Sends the pointer motion to the state machine as `PointerMotion` events,
+    /// and a `PointerRest` event when the pointer hasn't moved for `dwell`.
+    ///
+    /// The nodes are called on every motion, the key conditions of `define_keys!` are false on them.
+    pub fn track_pointer(&mut self, dwell: Duration) -> io::Result<()> {
+        if !self.client.track_pointer() {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "the pointer can't be tracked"));
+        }
+        if self.dwell.is_none() {
+            let timer = Timer::new()?;
+            self.watch(timer.fd(), DWELL)?;
+            self.dwell = Some((dwell, timer));
+        }
+        if let Some((after, _)) = &mut self.dwell {
+            *after = dwell;
+        }
+        self.watch_pointer()
+    }
+
+    pub fn remove_source(&mut self, id: SourceId) {
+        if let Some(source) = self.sources.get_mut(id.0).and_then(Option::take) {
+            self.unwatch(source.fd);
+        }
+    }
+
+    pub fn executor_mut(&mut self) -> &mut Executor<K, U> {
+        &mut self.executor
+    }
+
+    pub fn client_mut(&mut self) -> &mut C {
+        &mut self.client
+    }
+
+    pub fn run(mut self) -> ! {
+        loop {
+            self.executor.check_timeout(&mut self.client);
+            let handled = self.handle_display_events();
+            // a broken connection is always readable, it would spin
+            if !self.client.is_connected() {
+                self.reconnect();
+                continue;
+            }
+            let timeout = self
+                .executor
+                .deadline()
+                .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            self.deadline.set(timeout, None);
+            // handling the events could have queued more, see `handle_display_events`
+            let tokens = self.wait(handled.then_some(Duration::ZERO));
+            self.dispatch(tokens);
+        }
+    }
+
+    /// Handles the queued events, gives back whether there was any.
+    ///
+    /// xcb reads the events that arrive before a reply into its queue,
+    /// e.g. when a node grabs the keyboard or the pointer is queried.
+    /// The fd doesn't signal them anymore, so the loop must not block until the queue is empty.
+    fn handle_display_events(&mut self) -> bool {
+        let events = self.client.poll_for_events();
+        let handled = !events.is_empty();
+        for event in events {
+            if let DisplayServerEvent::PointerMotion(pointer) = event {
+                self.moved(pointer);
+            }
+            self.executor.next(event, &mut self.client);
+        }
+        handled
+    }
+
+    fn dispatch(&mut self, tokens: Vec<u64>) {
+        for token in tokens {
+            match token {
+                DISPLAY => {}
+                DEADLINE => {
+                    drain(self.deadline.fd());
+                }
+                DWELL => {
+                    if let Some((_, timer)) = &self.dwell {
+                        drain(timer.fd());
+                    }
+                    if let Some(pointer) = self.moved_to.take() {
+                        self.executor.next(DisplayServerEvent::PointerRest(pointer), &mut self.client);
+                    }
+                }
+                SHUTDOWN => self.shutdown(),
+                index => {
+                    // it could be removed by an earlier callback
+                    if let Some(Some(source)) = self.sources.get_mut(index as usize) {
+                        (source.callback)(&mut self.executor, &mut self.client);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Tries to connect again with growing delays, the other sources are still served meanwhile.
+    fn reconnect(&mut self) {
+        self.unwatch(self.display_fd);
+        if let Some(pointer_fd) = self.pointer_fd.take() {
+            self.unwatch(pointer_fd);
+        }
+        self.deadline.set(None, None);
+        let mut delay = FIRST_RECONNECT_DELAY;
+        loop {
+            println!("WARN: the display server connection is broken, reconnecting in {:?}", delay);
+            let until = Instant::now() + delay;
+            let mut now = Instant::now();
+            while now < until {
+                let tokens = self.wait(Some(until - now));
+                self.dispatch(tokens);
+                now = Instant::now();
+            }
+            if self.client.reconnect() {
+                break;
+            }
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+        println!("reconnected to the display server");
+        self.display_fd = self.client.connection_fd();
+        let display_fd = self.display_fd;
+        self.watch(display_fd, DISPLAY)
+            .unwrap_or_else(|err| panic!("Can't watch the display connection: {}", err));
+        self.watch_pointer()
+            .unwrap_or_else(|err| panic!("Can't watch the pointer: {}", err));
+        self.executor.regrab(&mut self.client);
+    }
+
+    /// The pointer motion could arrive on another connection than the keys.
+    fn watch_pointer(&mut self) -> io::Result<()> {
+        let pointer_fd = self.client.pointer_fd();
+        if pointer_fd != self.pointer_fd {
+            if let Some(old) = self.pointer_fd.take() {
+                self.unwatch(old);
+            }
+            if let Some(fd) = pointer_fd {
+                // it only wakes up the loop, the motion is read by `poll_for_events`
+                self.watch(fd, DISPLAY)?;
+            }
+            self.pointer_fd = pointer_fd;
+        }
+        Ok(())
+    }
+
+    /// Restarts the dwell time.
+    fn moved(&mut self, pointer: Pointer) {
+        if let Some((after, timer)) = &self.dwell {
+            timer.set(Some(*after), None);
+            self.moved_to = Some(pointer);
+        }
+    }
+
+    /// Releases the grabs, the started programs are kept running.
+    fn shutdown(&mut self) -> ! {
+        println!("shutting down");
+        self.client.ungrab_all();
+        std::process::exit(0)
+    }
+
+    fn insert(
+        &mut self,
+        fd: RawFd,
+        callback: Callback<C, K, U>,
+        timer: Option<Timer>,
+    ) -> io::Result<SourceId> {
+        let index = self
+            .sources
+            .iter()
+            .position(Option::is_none)
+            .unwrap_or(self.sources.len());
+        self.watch(fd, index as u64)?;
+        let source = Some(Source {
+            fd,
+            callback,
+            _timer: timer,
+        });
+        if index == self.sources.len() {
+            self.sources.push(source);
+        } else {
+            self.sources[index] = source;
+        }
+        Ok(SourceId(index))
+    }
+
+    fn watch(&self, fd: RawFd, token: u64) -> io::Result<()> {
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: token,
+        };
+        if unsafe { libc::epoll_ctl(self.epoll, libc::EPOLL_CTL_ADD, fd, &mut event) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn unwatch(&self, fd: RawFd) {
+        unsafe {
+            libc::epoll_ctl(self.epoll, libc::EPOLL_CTL_DEL, fd, std::ptr::null_mut());
+        }
+    }
+
+    /// Blocks until some of the sources become readable or the timeout is over,
+    /// gives back their tokens.
+    fn wait(&self, timeout: Option<Duration>) -> Vec<u64> {
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; MAX_EVENTS];
+        // rounded up, so it doesn't wake up right before the timeout
+        let timeout_ms = timeout.map_or(-1, |timeout| {
+            timeout.as_nanos().div_ceil(1_000_000).min(libc::c_int::MAX as u128) as libc::c_int
+        });
+        let count = unsafe {
+            libc::epoll_wait(self.epoll, events.as_mut_ptr(), MAX_EVENTS as libc::c_int, timeout_ms)
+        };
+        if count == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                println!("WARN: epoll_wait failed: {}", err);
+            }
+            return vec![];
+        }
+        events[..count as usize].iter().map(|event| event.u64).collect()
+    }
+}
+
+impl<C, K, U> Drop for EventLoop<C, K, U> {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.epoll);
+        }
+    }
+}
+
+/// Reads everything from a non-blocking file descriptor,
+/// gives back whether there was anything to read.
+pub fn drain(fd: RawFd) -> bool {
+    let mut buffer = [0u8; 64];
+    let mut drained = false;
+    while unsafe { libc::read(fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) } > 0 {
+        drained = true;
+    }
+    drained
+}
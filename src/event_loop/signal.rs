@@ -0,0 +1,56 @@
+use std::{
+    io,
+    os::unix::io::RawFd,
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Mutex,
+    },
+};
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_PIPE: AtomicI32 = AtomicI32::new(-1);
+/// Write ends of the signal pipes, indexed by the signal number
+static SIGNAL_WRITE_FDS: [AtomicI32; 65] = [NO_PIPE; 65];
+/// Read ends of the signal pipes, indexed by the signal number
+static SIGNAL_READ_FDS: Mutex<[RawFd; 65]> = Mutex::new([-1; 65]);
+
+/// A file descriptor that becomes readable when the signal arrives.
+///
+/// The signal handler is installed on the first call, later calls give back the same pipe.
+/// Use `drain` after it became readable.
+///
+/// A self-pipe is used instead of a signalfd, because a signalfd needs the signal to be blocked,
+/// and the blocked mask would be inherited by every thread and every started program.
+pub fn signal_pipe(signal: libc::c_int) -> RawFd {
+    let mut read_fds = SIGNAL_READ_FDS.lock().unwrap();
+    let index = signal as usize;
+    if read_fds[index] != -1 {
+        return read_fds[index];
+    }
+    unsafe {
+        let mut fds = [0; 2];
+        if libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) == -1 {
+            panic!("Can't create pipe for signal {}: {}", signal, io::Error::last_os_error());
+        }
+        read_fds[index] = fds[0];
+        SIGNAL_WRITE_FDS[index].store(fds[1], Ordering::Relaxed);
+
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART | libc::SA_NOCLDSTOP;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(signal, &action, std::ptr::null_mut());
+    }
+    read_fds[index]
+}
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    // only async-signal-safe calls are allowed here,
+    // and the errno of the interrupted code has to be kept
+    let fd = SIGNAL_WRITE_FDS[signal as usize].load(Ordering::Relaxed);
+    unsafe {
+        let errno = *libc::__errno_location();
+        libc::write(fd, [0u8].as_ptr() as *const libc::c_void, 1);
+        *libc::__errno_location() = errno;
+    }
+}
@@ -0,0 +1,51 @@
+use std::{io, os::unix::io::RawFd, time::Duration};
+
+/// A timerfd, it becomes readable when the timer expires.
+pub struct Timer {
+    fd: RawFd,
+}
+
+impl Timer {
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe {
+            libc::timerfd_create(libc::CLOCK_MONOTONIC, libc::TFD_NONBLOCK | libc::TFD_CLOEXEC)
+        };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { fd })
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// Expires after `after`, then in every `interval` if it's given.
+    /// The timer is stopped when `after` is `None`.
+    pub fn set(&self, after: Option<Duration>, interval: Option<Duration>) {
+        // a zero value would stop the timer
+        let after = after.map(|after| after.max(Duration::from_nanos(1)));
+        let spec = libc::itimerspec {
+            it_interval: timespec(interval.unwrap_or_default()),
+            it_value: timespec(after.unwrap_or_default()),
+        };
+        if unsafe { libc::timerfd_settime(self.fd, 0, &spec, std::ptr::null_mut()) } == -1 {
+            println!("WARN: could not set timer: {}", io::Error::last_os_error());
+        }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+fn timespec(duration: Duration) -> libc::timespec {
+    libc::timespec {
+        tv_sec: duration.as_secs() as libc::time_t,
+        tv_nsec: duration.subsec_nanos() as libc::c_long,
+    }
+}
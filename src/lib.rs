@@ -0,0 +1,11 @@
+//! A hot key daemon, the bindings are nodes of a state machine.
+//!
+//! Write a `start` node with `define_keys!` and `sequence!`,
+//! then drive it with an `EventLoop`, see `main.rs` for an example.
+
+pub mod config;
+pub mod display;
+pub mod event_loop;
+pub mod process;
+pub mod state_machine;
+pub mod tools;
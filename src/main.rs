@@ -1,29 +1,48 @@
-use crate::{state_machine::{define_keys, next, restart, run, wait, NodeOut}, tools::sequence};
-use display::DisplayServerClient;
+use std::time::Duration;
 
-mod display;
-mod state_machine;
-mod tools;
+use rhkd::{
+    config, display,
+    event_loop::EventLoop,
+    process,
+    state_machine::{define_keys, next, next_with_timeout, restart, run, wait, Executor, NodeOut},
+    tools::sequence,
+};
 
 define_keys! {
-    super_l => display::XK_Super_L,
-    esc => display::XK_Escape,
-    shift => display::XK_Shift_L,
-    c => display::XK_c,
-    n => display::XK_n,
-    w => display::XK_w,
-    a => display::XK_a,
-    j => display::XK_j,
-    s => display::XK_s,
-    l => display::XK_l,
-    m => display::XK_m,
-    d => display::XK_d,
-    k => display::XK_k,
-    u => display::XK_u,
-    h => display::XK_h,
-    p => display::XK_p,
-    r => display::XK_r,
-    long_i => display::XK_iacute
+    // grabbed, they start the bindings
+    start: {
+        super_l => display::XK_Super_L,
+        // only with super, the plain letters are typed without a detour
+        c [Mod4] => display::XK_c,
+        l [Mod4] => display::XK_l,
+        // the modifier is read from the event
+        m [Mod4] => display::XK_m,
+        equal [Mod4] => display::XK_equal,
+        minus [Mod4] => display::XK_minus,
+        long_i => display::XK_iacute
+    }
+    // received while the keyboard is grabbed in a mode
+    mode: {
+        esc => display::XK_Escape,
+        shift => display::XK_Shift_L,
+        n => display::XK_n,
+        w => display::XK_w,
+        a => display::XK_a,
+        j => display::XK_j,
+        s => display::XK_s,
+        d => display::XK_d,
+        k => display::XK_k,
+        u => display::XK_u,
+        h => display::XK_h,
+        p => display::XK_p,
+        r => display::XK_r
+    }
+    // grabbed only with super
+    buttons: {
+        button3 [Mod4] => display::BUTTON_RIGHT,
+        scroll_up [Mod4] => display::SCROLL_UP,
+        scroll_down [Mod4] => display::SCROLL_DOWN
+    }
 }
 
 struct UserState { count: i32 }
@@ -32,6 +51,11 @@ fn start(key: &KeyState, state: &mut UserState) -> NodeOut<KeyState, UserState>
 
     sequence! {
         key,
+        // super alone opens the launcher, it's still a modifier for the others
+        (tap super_l): "rofi -show drun";
+        // repeated while held, grabbed only with super
+        (equal) repeat "pactl set-sink-volume @DEFAULT_SINK@ +5%";
+        (minus) repeat "pactl set-sink-volume @DEFAULT_SINK@ -5%";
         (super_l && c) => {
             (shift && n): "nm-connection-editor";
             (n): "urxvt -e nmtui";
@@ -83,6 +107,8 @@ fn start(key: &KeyState, state: &mut UserState) -> NodeOut<KeyState, UserState>
             }
             if key.k() {
                 next!(|key, _state| {
+                    // the layout is set once, even if the key is held
+                    if key.repeated() { wait!() }
                     if key.esc() { restart!() }
                     if key.u() { run!(setxkbmap us) }
                     if key.h() { run!(setxkbmap hu) }
@@ -93,28 +119,82 @@ fn start(key: &KeyState, state: &mut UserState) -> NodeOut<KeyState, UserState>
         })
     }
 
-    // session control
+    // volume control, the step is captured by the node
+    if key.modifiers().mod4 && key.m() {
+        return volume(5);
+    }
+
+    // volume control with the scroll wheel
+    if key.modifiers().mod4 && key.scroll_up() { run!("pactl set-sink-volume @DEFAULT_SINK@ +5%") }
+    if key.modifiers().mod4 && key.scroll_down() { run!("pactl set-sink-volume @DEFAULT_SINK@ -5%") }
+
+    // gestures, drawn while super and the right button are held
+    if key.modifiers().mod4 && key.button3() {
+        next!(gesture)
+    }
+
+    // hot corner, the pointer has to rest there for a moment
+    if key.pointer_resting() && key.pointer().corner() == Some(display::Corner::TopLeft) {
+        run!("rofi -show window")
+    }
+
+    // session control, it is left after 3 seconds
     if key.super_l() && key.l() {
-        next!(|key, _state| {
+        next_with_timeout!(|key, _state| {
             if key.esc() { return NodeOut::Next(start) }
             if key.p() { run!(systemctl poweroff) }
             if key.l() { run!(systemctl suspend) }
             if key.r() { run!(systemctl reboot) }
             wait!()
-        })
+        }, 3000)
     }
 
     wait!()
 }
 
+#[rustfmt::skip]
+fn volume(step: u32) -> NodeOut<KeyState, UserState> {
+    NodeOut::closure(move |key: &KeyState, _state: &mut UserState| {
+        if key.esc() { restart!() }
+        // a held u or d keeps changing the volume with the autorepeat
+        if key.u() {
+            process::spawn_shell(&format!("pactl set-sink-volume @DEFAULT_SINK@ +{}%", step));
+            return volume(step);
+        }
+        if key.d() {
+            process::spawn_shell(&format!("pactl set-sink-volume @DEFAULT_SINK@ -{}%", step));
+            return volume(step);
+        }
+        wait!()
+    })
+}
+
+#[rustfmt::skip]
+fn gesture(key: &KeyState, _state: &mut UserState) -> NodeOut<KeyState, UserState> {
+    if key.esc() { restart!() }
+    match key.gesture() {
+        Some("L") => run!("bspc desktop -f prev"),
+        Some("R") => run!("bspc desktop -f next"),
+        Some("DU") => run!("rofi -show drun"),
+        // an unknown gesture, a click or a cancelled one
+        _ if !key.button3() => restart!(),
+        // the button events are hidden, so the pointer stays grabbed until the release
+        _ => next!(gesture),
+    }
+}
+
 fn main() {
     let mut x = display::X11Client::new();
-    let mut executor = state_machine::Executor::new(&mut x, start, UserState {
-        count: 0
-    });
-    loop {
-        if let Some(event) = x.wait_for_event() {
-            executor.next(event, &mut x);
+    process::notify_failures(true);
+    match config::config_path() {
+        Some(path) => config::run(x, path),
+        None => {
+            let executor = Executor::new(&mut x, start, UserState { count: 0 });
+            let mut event_loop = EventLoop::new(x, executor);
+            if let Err(err) = event_loop.track_pointer(Duration::from_millis(300)) {
+                println!("WARN: the hot corner is disabled: {}", err);
+            }
+            event_loop.run()
         }
     }
 }
@@ -0,0 +1,5 @@
+mod spawn;
+mod supervisor;
+
+pub use spawn::*;
+pub use supervisor::*;
@@ -0,0 +1,52 @@
+use std::{
+    ffi::OsStr,
+    io,
+    os::unix::process::CommandExt,
+    process::{Command, Stdio},
+};
+
+/// Runs a command line through `sh -c`, so pipes, `~` and variables work.
+/// Used by `run!("urxvt -e nmtui")`.
+pub fn spawn_shell(command: &str) {
+    let mut command_builder = Command::new("sh");
+    command_builder.arg("-c").arg(command);
+    spawn_detached(command_builder);
+}
+
+/// Executes the program directly, the first element is the program name.
+/// Used by `run!(urxvt "-e" nmtui)`.
+pub fn spawn<S: AsRef<OsStr>>(argv: &[S]) {
+    if let Some((program, args)) = argv.split_first() {
+        let mut command_builder = Command::new(program);
+        command_builder.args(args);
+        spawn_detached(command_builder);
+    }
+}
+
+fn spawn_detached(mut command: Command) {
+    command.stdin(Stdio::null());
+    let stderr = match super::stderr_capture() {
+        Ok(stderr) => stderr.try_clone().ok().map(|copy| {
+            command.stderr(copy);
+            stderr
+        }),
+        Err(err) => {
+            println!("WARN: stderr of {:?} won't be captured: {}", command, err);
+            None
+        }
+    };
+    // The child gets its own session, so it survives when the daemon is restarted
+    // and it doesn't receive the signals sent to our process group.
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    match command.spawn() {
+        Ok(child) => super::track(child, format!("{:?}", command), stderr),
+        Err(err) => println!("WARN: could not spawn {:?}: {}", command, err),
+    }
+}
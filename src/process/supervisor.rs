@@ -0,0 +1,129 @@
+use std::{
+    fs::File,
+    io,
+    os::unix::{
+        fs::FileExt,
+        io::FromRawFd,
+    },
+    process::{Child, ExitStatus},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use notify_rust::{Notification, Urgency};
+
+/// How much of the stderr is shown in the failure report
+const STDERR_TAIL_LINES: usize = 10;
+const STDERR_TAIL_BYTES: u64 = 4096;
+
+static CHILDREN: Mutex<Vec<TrackedChild>> = Mutex::new(Vec::new());
+static NOTIFY_FAILURES: AtomicBool = AtomicBool::new(false);
+
+/// A process started by `run!`, waiting to be reaped.
+struct TrackedChild {
+    child: Child,
+    command: String,
+    started: Instant,
+    /// The file the child writes its stderr into.
+    stderr: Option<File>,
+}
+
+/// Show a desktop notification when a launched command fails,
+/// not only a log line.
+pub fn notify_failures(enabled: bool) {
+    NOTIFY_FAILURES.store(enabled, Ordering::Relaxed);
+}
+
+/// An anonymous in-memory file for the stderr of a child.
+///
+/// A pipe would kill the child with SIGPIPE once the daemon is restarted,
+/// the file is kept alive by the child itself.
+pub(super) fn stderr_capture() -> io::Result<File> {
+    let fd = unsafe {
+        libc::memfd_create(
+            b"rhkd-stderr\0".as_ptr() as *const libc::c_char,
+            libc::MFD_CLOEXEC,
+        )
+    };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+pub(super) fn track(child: Child, command: String, stderr: Option<File>) {
+    println!("spawned {} with pid {}", command, child.id());
+    CHILDREN.lock().unwrap().push(TrackedChild {
+        child,
+        command,
+        started: Instant::now(),
+        stderr,
+    });
+}
+
+fn stderr_tail(stderr: &File) -> Vec<String> {
+    let len = stderr.metadata().map(|m| m.len()).unwrap_or(0);
+    let start = len.saturating_sub(STDERR_TAIL_BYTES);
+    let mut buffer = vec![0; (len - start) as usize];
+    let read = stderr.read_at(&mut buffer, start).unwrap_or(0);
+    let text = String::from_utf8_lossy(&buffer[..read]);
+    let lines: Vec<_> = text.lines().map(String::from).collect();
+    lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].to_vec()
+}
+
+/// Collects the exit status of the finished children, so they don't stay zombies,
+/// and reports the ones that failed. The `EventLoop` calls it on SIGCHLD.
+pub fn reap_children() {
+    let mut children = CHILDREN.lock().unwrap();
+    children.retain_mut(|tracked| match tracked.child.try_wait() {
+        Ok(Some(status)) => {
+            report_exit(tracked, status);
+            false
+        }
+        Ok(None) => true,
+        Err(err) => {
+            println!("WARN: could not wait for {}: {}", tracked.command, err);
+            false
+        }
+    });
+}
+
+fn report_exit(tracked: &TrackedChild, status: ExitStatus) {
+    if status.success() {
+        println!(
+            "{} (pid {}) finished after {:?}",
+            tracked.command,
+            tracked.child.id(),
+            tracked.started.elapsed()
+        );
+        return;
+    }
+    let stderr_tail = tracked.stderr.as_ref().map(stderr_tail).unwrap_or_default();
+    println!(
+        "WARN: {} (pid {}) failed after {:?} with {}",
+        tracked.command,
+        tracked.child.id(),
+        tracked.started.elapsed(),
+        status
+    );
+    for line in &stderr_tail {
+        println!("    {}", line);
+    }
+    if NOTIFY_FAILURES.load(Ordering::Relaxed) {
+        let mut notification = Notification::new();
+        notification
+            .summary(&format!("{} failed with {}", tracked.command, status))
+            .body(&stderr_tail.join("\n"))
+            .appname("rhkd")
+            .urgency(Urgency::Critical);
+        // the D-Bus call blocks, and the keyboard could be grabbed meanwhile
+        std::thread::spawn(move || {
+            if let Err(err) = notification.show() {
+                println!("WARN: could not show notification: {}", err);
+            }
+        });
+    }
+}
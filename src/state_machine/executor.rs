@@ -1,11 +1,41 @@
+use std::{
+    collections::HashSet,
+    panic::{self, AssertUnwindSafe},
+    time::{Duration, Instant},
+};
+
 use crate::display;
 
 use super::{transition::NodeOut, key_state::ManageKeyState};
 
+/// An action repeated while a key is held, see `NodeOut::Repeat`.
+struct Repeat {
+    keycode: display::Keycode,
+    next: Instant,
+    interval: Duration,
+    action: Box<dyn FnMut()>,
+}
+
 pub struct Executor<KeyState, UserState> {
     key_state: KeyState,
     user_state: UserState,
+    start: fn(&KeyState, &mut UserState) -> NodeOut<KeyState, UserState>,
     actual_node: NodeOut<KeyState, UserState>,
+    /// when to leave the actual node, and where to go
+    timeout: Option<(Instant, NodeOut<KeyState, UserState>)>,
+    /// the whole keyboard is grabbed while the actual node is not the start node
+    keyboard_grabbed: bool,
+    repeat: Option<Repeat>,
+    /// the key pressed by the actual event, a repeat is bound to it
+    pressed: Option<display::Keycode>,
+    /// the keys whose press was hidden, their release is hidden too,
+    /// a replay would end the grab and the next releases would go to the focused window
+    hidden: HashSet<display::Keycode>,
+    /// the press held back by `NodeOut::HoldBack`, and when to send it
+    held_back: Option<(display::Keycode, Instant)>,
+    /// an event was replayed in the start node, it ended the passive grab of the hidden keys,
+    /// so their releases could have been missed
+    resync_pending: bool,
 }
 impl<KeyState: ManageKeyState, UserState> Executor<KeyState, UserState> {
     pub fn new(
@@ -15,22 +45,272 @@ impl<KeyState: ManageKeyState, UserState> Executor<KeyState, UserState> {
     ) -> Self {
         Self {
             key_state: KeyState::new(client),
+            start,
             actual_node: NodeOut::Next(start),
+            timeout: None,
+            keyboard_grabbed: false,
+            repeat: None,
+            pressed: None,
+            hidden: HashSet::new(),
+            held_back: None,
+            resync_pending: false,
             user_state
         }
     }
+    /// For key states that need more than the display server to be created,
+    /// like `config::ConfigKeyState`.
+    pub fn with_key_state(
+        key_state: KeyState,
+        start: fn(&KeyState, &mut UserState) -> NodeOut<KeyState, UserState>,
+        user_state: UserState,
+    ) -> Self {
+        Self {
+            key_state,
+            start,
+            actual_node: NodeOut::Next(start),
+            timeout: None,
+            keyboard_grabbed: false,
+            repeat: None,
+            pressed: None,
+            hidden: HashSet::new(),
+            held_back: None,
+            resync_pending: false,
+            user_state
+        }
+    }
+    /// Goes back to the start node, like `restart!()` does.
+    pub fn restart(&mut self, client: &mut dyn display::DisplayServerClient) {
+        self.repeat = None;
+        self.send_held_back(client);
+        self.enter(NodeOut::Next(self.start), client);
+    }
+    fn enter(&mut self, node: NodeOut<KeyState, UserState>, client: &mut dyn display::DisplayServerClient) {
+        match node {
+            NodeOut::Timeout { node, after, then } => {
+                self.enter(*node, client);
+                self.timeout = Some((Instant::now() + after, *then));
+            }
+            // the replay is done by `next`
+            NodeOut::Replay(node) => self.enter(*node, client),
+            NodeOut::Repeat { node, delay, interval, action } => {
+                self.enter(*node, client);
+                // only a key can be held
+                self.repeat = self.pressed.map(|keycode| Repeat {
+                    keycode,
+                    next: Instant::now() + delay,
+                    interval,
+                    action,
+                });
+            }
+            node => {
+                self.actual_node = node;
+                self.timeout = None;
+                self.update_keyboard_grab(client);
+            }
+        }
+    }
+    /// Only the keys of the start node are grabbed,
+    /// the modes get every key by grabbing the whole keyboard.
+    fn update_keyboard_grab(&mut self, client: &mut dyn display::DisplayServerClient) {
+        // compared by address, the start node is a plain fn
+        let at_start = matches!(self.actual_node, NodeOut::Next(node) if node as usize == self.start as usize);
+        if at_start && self.keyboard_grabbed {
+            client.ungrab_keyboard();
+            self.keyboard_grabbed = false;
+            self.resync(client);
+        } else if !at_start && !self.keyboard_grabbed {
+            self.keyboard_grabbed = client.grab_keyboard();
+        }
+    }
+    /// Forgets the keys that are not held anymore, their releases were not seen.
+    fn resync(&mut self, client: &mut dyn display::DisplayServerClient) {
+        let pressed = client.pressed_keycodes();
+        self.hidden.retain(|keycode| pressed.contains(keycode));
+        self.key_state.resync(&pressed);
+        self.resync_pending = false;
+    }
+    fn replay(&mut self, e: display::DisplayServerEvent, client: &mut dyn display::DisplayServerClient) {
+        client.release_event(e, display::EventHandling::Replay);
+        // the pointer replays don't end the keyboard grabs
+        self.resync_pending |= !self.keyboard_grabbed && is_key_event(e);
+    }
+    /// The main loop has to call `check_timeout` at this time.
+    pub fn deadline(&self) -> Option<Instant> {
+        let timeout = self.timeout.as_ref().map(|(deadline, _)| *deadline);
+        let repeat = self.repeat.as_ref().map(|repeat| repeat.next);
+        let held_back = self.held_back.map(|(_, deadline)| deadline);
+        timeout.into_iter().chain(repeat).chain(held_back).min()
+    }
+    /// The held back press goes to the focused window, see `NodeOut::HoldBack`.
+    fn send_held_back(&mut self, client: &mut dyn display::DisplayServerClient) -> bool {
+        match self.held_back.take() {
+            Some((keycode, _)) => {
+                client.fake_key(keycode, true);
+                self.hidden.remove(&keycode);
+                // the fake press is replayed when it comes back through the grab
+                self.resync_pending |= !self.keyboard_grabbed;
+                true
+            }
+            None => false,
+        }
+    }
+    /// Leaves the actual node, if it has timed out, calls the repeated action if it's time,
+    /// and sends the held back press after its time.
+    pub fn check_timeout(&mut self, client: &mut dyn display::DisplayServerClient) {
+        if matches!(self.held_back, Some((_, deadline)) if deadline <= Instant::now()) {
+            self.send_held_back(client);
+        }
+        if let Some(repeat) = &mut self.repeat {
+            let now = Instant::now();
+            if repeat.next <= now {
+                (repeat.action)();
+                // a late wake-up doesn't cause a burst
+                repeat.next = (repeat.next + repeat.interval).max(now);
+            }
+        }
+        if matches!(self.timeout, Some((deadline, _)) if deadline <= Instant::now()) {
+            if let Some((_, then)) = self.timeout.take() {
+                self.enter(then, client);
+            }
+        }
+    }
+    /// Grabs the keys again and goes back to the start node,
+    /// after the display server connection has been restored.
+    pub fn regrab(&mut self, client: &mut dyn display::DisplayServerClient) {
+        self.key_state.regrab(client);
+        client.flush();
+        // the grabs of the old connection are gone
+        self.keyboard_grabbed = false;
+        self.repeat = None;
+        self.hidden.clear();
+        // the keyboard of the old connection is gone
+        self.held_back = None;
+        self.restart(client);
+    }
+    pub fn key_state_mut(&mut self) -> &mut KeyState {
+        &mut self.key_state
+    }
+    pub fn user_state_mut(&mut self) -> &mut UserState {
+        &mut self.user_state
+    }
     pub fn next(&mut self, e: display::DisplayServerEvent, client: &mut dyn display::DisplayServerClient) {
+        if let display::DisplayServerEvent::KeymapChange = e {
+            // the old keycodes could belong to other keys now, the actual node is kept
+            self.key_state.regrab(client);
+            client.flush();
+            return;
+        }
+        match (e, &self.repeat) {
+            // the X autorepeat of the repeated key, the repeat has its own rate
+            (display::DisplayServerEvent::KeyRepeat(keycode, _), Some(repeat)) if keycode == repeat.keycode => {
+                client.release_event(e, display::EventHandling::Hide);
+                return;
+            }
+            (display::DisplayServerEvent::KeyPress(..), Some(_)) => self.repeat = None,
+            (display::DisplayServerEvent::KeyRelease(keycode, _), Some(repeat)) if keycode == repeat.keycode => {
+                self.repeat = None;
+            }
+            _ => {}
+        }
+        self.pressed = match e {
+            display::DisplayServerEvent::KeyPress(keycode, _) => Some(keycode),
+            _ => None,
+        };
+        // before a press, the queued releases would be dropped by the query too
+        if self.resync_pending && self.pressed.is_some() {
+            self.resync(client);
+        }
         self.key_state.update(e);
-        match self.actual_node {
-            NodeOut::Next(fnptr) => {
-                if let NodeOut::Next(new_fnptr) = (fnptr)(&self.key_state, &mut self.user_state) {
-                    client.release_event(e, display::EventHandling::Hide);
-                    self.actual_node = NodeOut::Next(new_fnptr)
-                } else {
-                    client.release_event(e, display::EventHandling::Replay);
+        let key_state = &self.key_state;
+        let user_state = &mut self.user_state;
+        let actual_node = &mut self.actual_node;
+        // the event has to be released even if the node panics, otherwise the keyboard stays frozen
+        let out = panic::catch_unwind(AssertUnwindSafe(|| match actual_node {
+            NodeOut::Next(fnptr) => (fnptr)(key_state, user_state),
+            NodeOut::Closure(closure) => closure(key_state, user_state),
+            NodeOut::Timeout { .. }
+            | NodeOut::Repeat { .. }
+            | NodeOut::Replay(_)
+            | NodeOut::HoldBack(_)
+            | NodeOut::None => {
+                unreachable!()
+            }
+        }));
+        let out = match out {
+            Ok(out) => out,
+            Err(_) => {
+                println!("WARN: the node panicked, going back to the start node");
+                self.replay(e, client);
+                self.restart(client);
+                return;
+            }
+        };
+        let (out, replayed) = match out {
+            NodeOut::Replay(node) => (*node, true),
+            out => (out, false),
+        };
+        let out = match (out, e) {
+            (NodeOut::HoldBack(after), display::DisplayServerEvent::KeyPress(..)) if !self.keyboard_grabbed => {
+                NodeOut::HoldBack(after)
+            }
+            (NodeOut::HoldBack(_), _) => NodeOut::None,
+            (out, _) => out,
+        };
+        // a transition takes the held back press with the event, like the last key of a chord,
+        // the pointer events don't send it, they come all the time while the pointer is tracked
+        let sent = match out {
+            NodeOut::None | NodeOut::HoldBack(_) if is_key_event(e) => self.send_held_back(client),
+            NodeOut::None | NodeOut::HoldBack(_) => false,
+            _ => {
+                self.held_back = None;
+                false
+            }
+        };
+        let press_hidden = match e {
+            display::DisplayServerEvent::KeyRelease(keycode, _) => self.hidden.remove(&keycode),
+            _ => false,
+        };
+        let resent = match e {
+            display::DisplayServerEvent::KeyPress(keycode, _) | display::DisplayServerEvent::KeyRepeat(keycode, _) => {
+                Some((keycode, true))
+            }
+            display::DisplayServerEvent::KeyRelease(keycode, _) => Some((keycode, false)),
+            _ => None,
+        };
+        match (out, resent) {
+            // a replayed event would overtake the fake press, so it's faked too
+            (NodeOut::None, Some((keycode, press))) if sent && !press_hidden => {
+                client.fake_key(keycode, press);
+                client.release_event(e, display::EventHandling::Hide);
+            }
+            (NodeOut::None, _) if press_hidden => client.release_event(e, display::EventHandling::Hide),
+            (NodeOut::None, _) => self.replay(e, client),
+            (NodeOut::HoldBack(after), Some((keycode, _))) => {
+                self.hidden.insert(keycode);
+                self.held_back = Some((keycode, Instant::now() + after));
+                client.release_event(e, display::EventHandling::Hide);
+            }
+            (next_node, _) if replayed && !press_hidden => {
+                self.replay(e, client);
+                self.enter(next_node, client)
+            }
+            (next_node, _) => {
+                if let display::DisplayServerEvent::KeyPress(keycode, _) = e {
+                    self.hidden.insert(keycode);
                 }
+                client.release_event(e, display::EventHandling::Hide);
+                self.enter(next_node, client)
             }
-            NodeOut::None => unreachable!(),
         }
+        self.pressed = None;
     }
 }
+
+fn is_key_event(e: display::DisplayServerEvent) -> bool {
+    matches!(
+        e,
+        display::DisplayServerEvent::KeyPress(..)
+            | display::DisplayServerEvent::KeyRepeat(..)
+            | display::DisplayServerEvent::KeyRelease(..)
+    )
+}
@@ -1,24 +1,85 @@
+/// Stays in the actual node, the event is replayed to the other clients.
+#[macro_export]
 macro_rules! wait {
     () => {
-        return NodeOut::None
+        return $crate::state_machine::NodeOut::None
     };
 }
+/// Goes to the given node, the event is hidden from the other clients.
+#[macro_export]
 macro_rules! next {
     ($a:expr) => {
-        return NodeOut::Next($a)
+        return $crate::state_machine::NodeOut::Next($a)
     };
 }
+/// Goes to the given node, and back to the start node
+/// after the given milliseconds without a transition.
+#[macro_export]
+macro_rules! next_with_timeout {
+    ($a:expr, $ms:expr) => {
+        return $crate::state_machine::NodeOut::with_timeout(
+            $crate::state_machine::NodeOut::Next($a),
+            ::std::time::Duration::from_millis($ms),
+            $crate::state_machine::NodeOut::Next(start),
+        )
+    };
+}
+/// Goes to a node that captures its environment,
+/// the types of the parameters have to be written out:
+/// `next_closure!(move |key: &KeyState, _state: &mut UserState| { ... })`
+#[macro_export]
+macro_rules! next_closure {
+    ($a:expr) => {
+        return $crate::state_machine::NodeOut::closure($a)
+    };
+}
+/// Goes back to the start node, which has to be called `start`.
+#[macro_export]
 macro_rules! restart {
     () => {
-        return NodeOut::Next(start)
+        return $crate::state_machine::NodeOut::Next(start)
     };
 }
+/// Starts a program and goes back to the start node.
+///
+/// A single string literal is a shell command line: `run!("urxvt -e nmtui")`,
+/// a list of tokens is executed directly as argv: `run!(urxvt "-e" nmtui)`.
+#[macro_export]
 macro_rules! run {
-    ($($token:tt)*) => {{
-        restart!()
+    (@arg $arg:ident) => {
+        stringify!($arg)
+    };
+    (@arg $arg:literal) => {
+        $arg
+    };
+    ($command:literal) => {{
+        $crate::process::spawn_shell($command);
+        $crate::restart!()
+    }};
+    ($($arg:tt)+) => {{
+        $crate::process::spawn(&[$($crate::run!(@arg $arg)),+]);
+        $crate::restart!()
+    }};
+}
+/// Starts a shell command like `run!`, and again while the key is held down,
+/// after 300 ms in every 80 ms: `repeat!("xbacklight -inc 5")`,
+/// or with the given milliseconds: `repeat!("xbacklight -inc 5", 500, 100)`.
+#[macro_export]
+macro_rules! repeat {
+    ($command:literal) => {
+        $crate::repeat!(@start $command, $crate::state_machine::REPEAT_DELAY, $crate::state_machine::REPEAT_INTERVAL)
+    };
+    ($command:literal, $delay:expr, $interval:expr) => {
+        $crate::repeat!(
+            @start $command,
+            ::std::time::Duration::from_millis($delay),
+            ::std::time::Duration::from_millis($interval)
+        )
+    };
+    (@start $command:literal, $delay:expr, $interval:expr) => {{
+        $crate::process::spawn_shell($command);
+        return $crate::state_machine::NodeOut::Next(start)
+            .with_repeat($delay, $interval, || $crate::process::spawn_shell($command))
     }};
 }
-pub(crate) use next;
-pub(crate) use restart;
-pub(crate) use run;
-pub(crate) use wait;
\ No newline at end of file
+pub use crate::{next, next_closure, next_with_timeout, repeat, restart, run, wait};
@@ -0,0 +1,148 @@
+/// The distance in pixels that the pointer has to move for a new direction.
+pub const DEFAULT_GESTURE_DISTANCE: u16 = 30;
+
+/// Recognizes a stroke of the pointer as a sequence of directions,
+/// like `"LR"` for left then right, or `"DU"` for down then up.
+#[derive(Clone, Debug)]
+pub struct Gesture {
+    min_distance: i32,
+    drawing: bool,
+    /// the point where the last direction was recognized
+    anchor: Option<(i32, i32)>,
+    directions: String,
+}
+
+impl Gesture {
+    pub fn new(min_distance: u16) -> Self {
+        Self {
+            min_distance: i32::from(min_distance.max(1)),
+            drawing: false,
+            anchor: None,
+            directions: String::new(),
+        }
+    }
+
+    /// Starts a new stroke, the first point is the next `add`.
+    pub fn begin(&mut self) {
+        self.drawing = true;
+        self.anchor = None;
+        self.directions.clear();
+    }
+
+    pub fn add(&mut self, x: i16, y: i16) {
+        if !self.drawing {
+            return;
+        }
+        let point = (i32::from(x), i32::from(y));
+        let (anchor_x, anchor_y) = *self.anchor.get_or_insert(point);
+        let (dx, dy) = (point.0 - anchor_x, point.1 - anchor_y);
+        if dx.abs().max(dy.abs()) < self.min_distance {
+            return;
+        }
+        // the y axis points down on the screen
+        let direction = match (dx.abs() > dy.abs(), dx > 0, dy > 0) {
+            (true, true, _) => 'R',
+            (true, false, _) => 'L',
+            (false, _, true) => 'D',
+            (false, _, false) => 'U',
+        };
+        if !self.directions.ends_with(direction) {
+            self.directions.push(direction);
+        }
+        self.anchor = Some(point);
+    }
+
+    /// Ends the stroke, gives back its directions.
+    /// `None` if it was cancelled, or the pointer has not moved enough, like a simple click.
+    pub fn end(&mut self) -> Option<String> {
+        let drawing = std::mem::replace(&mut self.drawing, false);
+        if !drawing || self.directions.is_empty() {
+            return None;
+        }
+        Some(std::mem::take(&mut self.directions))
+    }
+
+    /// Forgets the stroke, `end` gives back `None`.
+    pub fn cancel(&mut self) {
+        self.drawing = false;
+        self.directions.clear();
+    }
+
+    pub fn is_drawing(&self) -> bool {
+        self.drawing
+    }
+}
+
+impl Default for Gesture {
+    fn default() -> Self {
+        Self::new(DEFAULT_GESTURE_DISTANCE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recognize(min_distance: u16, points: &[(i16, i16)]) -> Option<String> {
+        let mut gesture = Gesture::new(min_distance);
+        gesture.begin();
+        for (x, y) in points {
+            gesture.add(*x, *y);
+        }
+        gesture.end()
+    }
+
+    #[test]
+    fn left_then_right() {
+        let points = [(500, 300), (490, 302), (460, 305), (430, 303), (445, 300), (480, 298), (520, 301)];
+        assert_eq!(recognize(30, &points).as_deref(), Some("LR"));
+    }
+
+    #[test]
+    fn down_then_up() {
+        let points = [(100, 100), (102, 120), (101, 150), (99, 180), (100, 160), (103, 120), (101, 90)];
+        assert_eq!(recognize(30, &points).as_deref(), Some("DU"));
+    }
+
+    #[test]
+    fn repeated_direction_is_merged() {
+        let points = [(0, 0), (40, 0), (80, 5), (120, 3), (160, 0)];
+        assert_eq!(recognize(30, &points).as_deref(), Some("R"));
+    }
+
+    #[test]
+    fn jitter_below_min_distance_is_a_click() {
+        let points = [(200, 200), (203, 198), (198, 204), (201, 199)];
+        assert_eq!(recognize(30, &points), None);
+    }
+
+    #[test]
+    fn min_distance_is_configurable() {
+        let points = [(0, 0), (0, 20), (20, 20)];
+        assert_eq!(recognize(30, &points), None);
+        assert_eq!(recognize(15, &points).as_deref(), Some("DR"));
+    }
+
+    #[test]
+    fn cancel() {
+        let mut gesture = Gesture::new(30);
+        gesture.begin();
+        gesture.add(0, 0);
+        gesture.add(-50, 0);
+        gesture.cancel();
+        gesture.add(-100, 0);
+        assert_eq!(gesture.end(), None);
+    }
+
+    #[test]
+    fn points_outside_of_a_stroke_are_ignored() {
+        let mut gesture = Gesture::new(30);
+        gesture.add(0, 0);
+        gesture.add(100, 0);
+        gesture.begin();
+        gesture.add(100, 0);
+        gesture.add(100, -40);
+        assert_eq!(gesture.end().as_deref(), Some("U"));
+        assert!(!gesture.is_drawing());
+    }
+}
@@ -0,0 +1,276 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use crate::display::{DisplayServerEvent, Keycode};
+
+/// A key held down longer than this is not a tap.
+pub const TAP_DURATION: Duration = Duration::from_millis(500);
+/// The next tap has to be pressed in this time after the release of the previous one,
+/// to count as a double tap.
+pub const TAP_WINDOW: Duration = Duration::from_millis(300);
+/// The keys of a chord have to be pressed in this time.
+pub const CHORD_WINDOW: Duration = Duration::from_millis(30);
+
+/// A key released by the last event.
+#[derive(Clone, Copy, Debug)]
+struct Release {
+    keycode: Keycode,
+    held: Duration,
+    /// no other key or button was pressed while it was held
+    alone: bool,
+}
+
+/// The press and release times of the keys, to tell a tap from a hold,
+/// and a chord from keys pressed one after the other.
+///
+/// Only the events that the state machine gets are counted.
+/// In the start node the release of a replayed press goes to the focused window,
+/// so `sequence!` holds back the press of a tapped key pressed alone, then every key comes here until its release.
+#[derive(Debug)]
+pub struct KeyHistory {
+    /// when the held keys were pressed, and whether they are still alone
+    pressed: HashMap<Keycode, (Instant, bool)>,
+    /// set only by the event of the release
+    released: Option<Release>,
+    /// the keys pressed since every key was released
+    together: HashSet<Keycode>,
+    /// set only by the release of the last held key, the keys that were pressed together
+    released_together: HashSet<Keycode>,
+    /// the last tapped key, how many times it was tapped in a row, and the release of the last tap
+    taps: Option<(Keycode, usize, Instant)>,
+    tap_window: Duration,
+    chord_window: Duration,
+}
+
+impl KeyHistory {
+    pub fn new(tap_window: Duration, chord_window: Duration) -> Self {
+        Self {
+            pressed: HashMap::new(),
+            released: None,
+            together: HashSet::new(),
+            released_together: HashSet::new(),
+            taps: None,
+            tap_window,
+            chord_window,
+        }
+    }
+
+    pub fn update(&mut self, e: DisplayServerEvent) {
+        let now = Instant::now();
+        self.released = None;
+        self.released_together.clear();
+        match e {
+            DisplayServerEvent::KeyPress(keycode, _) => {
+                self.interrupt();
+                self.together.insert(keycode);
+                // another key, or too late for the next tap
+                if self.taps.is_some_and(|(tapped, _, at)| {
+                    tapped != keycode || now.duration_since(at) > self.tap_window
+                }) {
+                    self.taps = None;
+                }
+                // the release of an earlier press could have been missed
+                self.pressed.insert(keycode, (now, true));
+            }
+            DisplayServerEvent::KeyRelease(keycode, _) => {
+                self.released = self.pressed.remove(&keycode).map(|(at, alone)| Release {
+                    keycode,
+                    held: now.duration_since(at),
+                    alone,
+                });
+                if self.pressed.is_empty() {
+                    self.released_together = std::mem::take(&mut self.together);
+                }
+                self.taps = match self.released {
+                    Some(release) if release.is_tap() => {
+                        let count = self.taps.map_or(0, |(_, count, _)| count);
+                        Some((keycode, count + 1, now))
+                    }
+                    _ => None,
+                };
+            }
+            DisplayServerEvent::ButtonPress(..) => {
+                self.interrupt();
+                self.taps = None;
+            }
+            _ => {}
+        }
+    }
+
+    fn interrupt(&mut self) {
+        for (_, alone) in self.pressed.values_mut() {
+            *alone = false;
+        }
+    }
+
+    /// The last event released one of the keycodes, which was pressed alone and shortly.
+    pub fn tapped(&self, keycodes: &[Keycode]) -> bool {
+        self.released
+            .is_some_and(|release| release.is_tap() && keycodes.contains(&release.keycode))
+    }
+
+    /// One of the keycodes is held down, and no other key is.
+    pub fn held_alone(&self, keycodes: &[Keycode]) -> bool {
+        self.pressed.len() == 1 && keycodes.iter().any(|keycode| self.pressed.contains_key(keycode))
+    }
+
+    /// The last event released one of the keycodes.
+    pub fn released(&self, keycodes: &[Keycode]) -> bool {
+        self.released
+            .is_some_and(|release| keycodes.contains(&release.keycode))
+    }
+
+    /// The last event released the last held key, and the keys were pressed together before.
+    pub fn all_released(&self, keys: &[&[Keycode]]) -> bool {
+        !self.released_together.is_empty()
+            && keys.iter().all(|keycodes| {
+                keycodes
+                    .iter()
+                    .any(|keycode| self.released_together.contains(keycode))
+            })
+    }
+
+    /// How many times one of the keycodes was tapped in a row, on the release of the last tap,
+    /// otherwise 0. A double tap is counted as 1 on its first release,
+    /// so a binding of a single tap fires before the double tap.
+    pub fn taps(&self, keycodes: &[Keycode]) -> usize {
+        match self.taps {
+            Some((keycode, count, _)) if self.tapped(&[keycode]) && keycodes.contains(&keycode) => count,
+            _ => 0,
+        }
+    }
+
+    /// One of the keycodes is held down at least for `duration`,
+    /// or the last event released it after that long.
+    pub fn held_for(&self, keycodes: &[Keycode], duration: Duration) -> bool {
+        let held = keycodes.iter().any(|keycode| {
+            self.pressed
+                .get(keycode)
+                .is_some_and(|(at, _)| at.elapsed() >= duration)
+        });
+        held || self.released.is_some_and(|release| {
+            release.held >= duration && keycodes.contains(&release.keycode)
+        })
+    }
+
+    /// When the key was pressed, if one of its keycodes is held down.
+    fn pressed_at(&self, keycodes: &[Keycode]) -> Option<Instant> {
+        keycodes
+            .iter()
+            .filter_map(|keycode| self.pressed.get(keycode))
+            .map(|(at, _)| *at)
+            .min()
+    }
+
+    /// The keys are held down, and they were pressed in the given order.
+    pub fn pressed_in_order(&self, keys: &[&[Keycode]]) -> bool {
+        let times: Option<Vec<_>> = keys.iter().map(|keycodes| self.pressed_at(keycodes)).collect();
+        times.is_some_and(|times| times.windows(2).all(|pair| pair[0] <= pair[1]))
+    }
+
+    /// The keys are held down, and they were pressed at the same time in any order,
+    /// within the chord window.
+    pub fn chord(&self, keys: &[&[Keycode]]) -> bool {
+        let times: Option<Vec<_>> = keys.iter().map(|keycodes| self.pressed_at(keycodes)).collect();
+        times.is_some_and(|times| match (times.iter().min(), times.iter().max()) {
+            (Some(first), Some(last)) => last.duration_since(*first) <= self.chord_window,
+            _ => false,
+        })
+    }
+
+    pub fn chord_window(&self) -> Duration {
+        self.chord_window
+    }
+
+    /// Forgets the keys that are not held anymore, see `ManageKeyState::resync`.
+    pub fn resync(&mut self, pressed: &[Keycode]) {
+        self.pressed.retain(|keycode, _| pressed.contains(keycode));
+        self.together.retain(|keycode| pressed.contains(keycode));
+        self.released = None;
+        self.released_together.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.pressed.clear();
+        self.released = None;
+        self.together.clear();
+        self.released_together.clear();
+        self.taps = None;
+    }
+}
+
+impl Default for KeyHistory {
+    fn default() -> Self {
+        Self::new(TAP_WINDOW, CHORD_WINDOW)
+    }
+}
+
+impl Release {
+    fn is_tap(&self) -> bool {
+        self.alone && self.held < TAP_DURATION
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::Modifiers;
+
+    const ESC: Keycode = 9;
+    const Q: Keycode = 24;
+
+    fn press(history: &mut KeyHistory, keycode: Keycode) {
+        history.update(DisplayServerEvent::KeyPress(keycode, Modifiers::default()));
+    }
+
+    fn release(history: &mut KeyHistory, keycode: Keycode) {
+        history.update(DisplayServerEvent::KeyRelease(keycode, Modifiers::default()));
+    }
+
+    #[test]
+    fn triple_tap() {
+        let mut history = KeyHistory::default();
+        for count in 1..=3 {
+            press(&mut history, ESC);
+            release(&mut history, ESC);
+            assert_eq!(history.taps(&[ESC]), count);
+        }
+    }
+
+    #[test]
+    fn another_key_breaks_the_taps() {
+        let mut history = KeyHistory::default();
+        press(&mut history, ESC);
+        release(&mut history, ESC);
+        press(&mut history, Q);
+        release(&mut history, Q);
+        press(&mut history, ESC);
+        release(&mut history, ESC);
+        assert_eq!(history.taps(&[ESC]), 1);
+    }
+
+    #[test]
+    fn taps_after_a_replayed_press() {
+        let mut history = KeyHistory::default();
+        // its release went to the focused window
+        press(&mut history, ESC);
+        for count in 1..=2 {
+            press(&mut history, ESC);
+            release(&mut history, ESC);
+            assert_eq!(history.taps(&[ESC]), count);
+        }
+    }
+
+    #[test]
+    fn held_key_is_not_a_tap() {
+        let mut history = KeyHistory::default();
+        press(&mut history, ESC);
+        press(&mut history, Q);
+        release(&mut history, Q);
+        release(&mut history, ESC);
+        assert!(!history.tapped(&[ESC]));
+        assert_eq!(history.taps(&[ESC]), 0);
+    }
+}
@@ -1,65 +1,350 @@
 pub trait ManageKeyState {
     fn new(client: &mut dyn display::DisplayServerClient) -> Self;
     fn update(&mut self, e: display::DisplayServerEvent);
+    /// Grabs the keys again, e.g. after reconnecting to the display server
+    /// or a keymap change. The old keycodes are ungrabbed first.
+    fn regrab(&mut self, client: &mut dyn display::DisplayServerClient);
+    /// Replaces the pressed keys, the releases could have been missed
+    /// since the keyboard is not grabbed anymore, or a replay ended the grab of a hidden press.
+    fn resync(&mut self, pressed: &[display::Keycode]);
 }
 
 pub const SIZE_OF_THE_ACTIVE_KEYS: usize = 100;
+/// Generates the `KeyState`, with a method for every key that tells whether it is pressed,
+/// a key without a keycode in the layout is never pressed,
+/// and `modifiers()` to get the modifiers held down during the last event.
+/// `pointer()` gives the last pointer position, if the event loop tracks the pointer.
+/// The nodes are called with the pointer events too, the held keys don't match on them.
+/// `gesture()` gives the directions drawn while a button was held, like `"LR"`,
+/// on the release of the button. Pressing another button cancels the gesture.
+/// `tapped(Key::super_l)` tells whether the last event released a key that was pressed alone and shortly,
+/// `held_for(Key::esc, duration)` whether a key is held down for a while,
+/// `taps(Key::esc)` how many times it was tapped in a row,
+/// `ordered(&[Key::a, Key::b])` whether the keys are held and were pressed in this order,
+/// `chord(&[Key::s, Key::t])` whether they were pressed at the same time, see `KeyHistory`.
+/// `event()` is the event that the node is called with,
+/// `pressed(Key::c)` and `released(Key::c)` tell whether it pressed or released the key,
+/// `pressed_alone(Key::c)` whether it pressed the key while no other key is held,
+/// `all_released(&[Key::super_l, Key::c])` whether it released the last key of the combo.
+///
+/// The keys are grabbed, so they can start a binding from the start node.
+/// A key can be grabbed only with the given modifiers, like `n [Mod4 | Shift] => display::XK_n`,
+/// otherwise it is grabbed with any modifiers.
+/// The keys used only in modes can be listed separately, they are not grabbed,
+/// the whole keyboard is grabbed while the state machine is in a mode.
+/// The mouse buttons are grabbed like the start keys:
+/// ```ignore
+/// define_keys! {
+///     start: { super_l => display::XK_Super_L, n [Mod4 | Shift] => display::XK_n }
+///     mode: { esc => display::XK_Escape, k => display::XK_k }
+///     buttons: { button3 [Mod4] => display::BUTTON_RIGHT, scroll_up => display::SCROLL_UP }
+///     // optional, the pixels needed for a direction
+///     gesture_distance: 50
+///     // optional, the milliseconds between the taps of a double tap
+///     tap_window: 250
+///     // optional, the milliseconds between the first and the last key of a chord
+///     chord_window: 40
+/// }
+/// ```
+#[macro_export]
 macro_rules! define_keys {
-    ($($name:ident => $key:expr),* $(,)?) => {
+    (
+        start: { $($name:ident $([$($modifier:ident)|+])? => $key:expr),* $(,)? }
+        mode: { $($mode_name:ident => $mode_key:expr),* $(,)? }
+        $(buttons: { $($button_name:ident $([$($button_modifier:ident)|+])? => $button:expr),* $(,)? })? $(,)?
+        $(gesture_distance: $gesture_distance:literal)? $(,)?
+        $(tap_window: $tap_window:literal)? $(,)?
+        $(chord_window: $chord_window:literal)? $(,)?
+    ) => {
+        /// Names the keys for the methods that take a key, like `KeyState::tapped`.
+        #[allow(non_camel_case_types)]
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub enum Key {
+            $( $name, )*
+            $( $mode_name, )*
+        }
+
         pub struct KeyState {
             // keycode: a number marking the physical place of keys
-            keycodes: std::collections::HashSet<crate::display::Keycode>,
+            keycodes: std::collections::HashSet<$crate::display::Keycode>,
+            modifiers: $crate::display::Modifiers,
             // keysym: a number assigned to the name of the key (understandable by both programs and humans). Like enter.
             // 1 keysym could need multiple keycodes
             // keys:
-            $( $name: Vec<crate::display::Keycode>, )*
+            $( $name: Vec<$crate::display::Keycode>, )*
+            $( $mode_name: Vec<$crate::display::Keycode>, )*
+            // the pressed mouse buttons
+            buttons: std::collections::HashSet<$crate::display::Button>,
+            pointer: $crate::display::Pointer,
+            // the pointer hasn't moved since the dwell time
+            pointer_resting: bool,
+            gesture: $crate::state_machine::Gesture,
+            // set only on the button release that finished a gesture
+            finished_gesture: Option<String>,
+            history: $crate::state_machine::KeyHistory,
+            event: Option<$crate::display::DisplayServerEvent>,
         }
 
         impl KeyState {
 
             // keys:
             $(
-                fn $name(&self)-> bool {
-                    println!("{:?}", self.$name);
-                    println!("{:?}", self.keycodes);
-                    self.$name.iter().map(|code| self.keycodes.contains(code)).all(|r| r)
+                pub fn $name(&self)-> bool {
+                    !self.pointer_event() && !self.$name.is_empty() && self.$name.iter().map(|code| self.keycodes.contains(code)).all(|r| r)
                 }
             )*
-        }
+            $(
+                pub fn $mode_name(&self)-> bool {
+                    !self.pointer_event() && !self.$mode_name.is_empty() && self.$mode_name.iter().map(|code| self.keycodes.contains(code)).all(|r| r)
+                }
+            )*
+
+            $($(
+                pub fn $button_name(&self)-> bool {
+                    self.buttons.contains(&$button)
+                }
+            )*)?
+
+            pub fn modifiers(&self) -> $crate::display::Modifiers {
+                self.modifiers
+            }
+
+            pub fn pointer(&self) -> $crate::display::Pointer {
+                self.pointer
+            }
+
+            pub fn pointer_resting(&self) -> bool {
+                self.pointer_resting
+            }
+
+            pub fn gesture(&self) -> Option<&str> {
+                self.finished_gesture.as_deref()
+            }
 
-        impl crate::state_machine::ManageKeyState for KeyState {
+            pub fn tapped(&self, key: Key) -> bool {
+                self.history.tapped(self.keycodes_of(key))
+            }
+
+            pub fn held_for(&self, key: Key, duration: std::time::Duration) -> bool {
+                !self.pointer_event() && self.history.held_for(self.keycodes_of(key), duration)
+            }
+
+            pub fn taps(&self, key: Key) -> usize {
+                self.history.taps(self.keycodes_of(key))
+            }
+
+            pub fn ordered(&self, keys: &[Key]) -> bool {
+                let keycodes: Vec<_> = keys.iter().map(|key| self.keycodes_of(*key)).collect();
+                !self.pointer_event() && self.history.pressed_in_order(&keycodes)
+            }
+
+            pub fn chord(&self, keys: &[Key]) -> bool {
+                let keycodes: Vec<_> = keys.iter().map(|key| self.keycodes_of(*key)).collect();
+                !self.pointer_event() && self.history.chord(&keycodes)
+            }
+
+            pub fn chord_window(&self) -> std::time::Duration {
+                self.history.chord_window()
+            }
+
+            pub fn event(&self) -> Option<$crate::display::DisplayServerEvent> {
+                self.event
+            }
+
+            /// The last event is the autorepeat of a held key.
+            /// The nodes are called with the repeats too, they can skip them by this.
+            pub fn repeated(&self) -> bool {
+                matches!(self.event, Some($crate::display::DisplayServerEvent::KeyRepeat(..)))
+            }
 
-            fn new(client: &mut dyn crate::display::DisplayServerClient) -> Self {
-                Self {
-                    keycodes: std::collections::HashSet::with_capacity(crate::state_machine::SIZE_OF_THE_ACTIVE_KEYS),
-                    $( $name: crate::state_machine::grab_keysym(client, $key),)*
+            /// The last event pressed the key, the autorepeat included.
+            pub fn pressed(&self, key: Key) -> bool {
+                match self.event {
+                    Some($crate::display::DisplayServerEvent::KeyPress(keycode, _))
+                    | Some($crate::display::DisplayServerEvent::KeyRepeat(keycode, _)) => {
+                        self.keycodes_of(key).contains(&keycode)
+                    }
+                    _ => false,
                 }
             }
-            fn update(&mut self, e: crate::display::DisplayServerEvent) {
+
+            /// The last event pressed the key while no other key is held, the autorepeat excluded.
+            pub fn pressed_alone(&self, key: Key) -> bool {
+                matches!(self.event, Some($crate::display::DisplayServerEvent::KeyPress(..)))
+                    && self.pressed(key)
+                    && self.history.held_alone(self.keycodes_of(key))
+            }
+
+            pub fn released(&self, key: Key) -> bool {
+                self.history.released(self.keycodes_of(key))
+            }
+
+            pub fn all_released(&self, keys: &[Key]) -> bool {
+                let keycodes: Vec<_> = keys.iter().map(|key| self.keycodes_of(*key)).collect();
+                self.history.all_released(&keycodes)
+            }
+
+            /// The held keys don't match on the pointer events,
+            /// otherwise moving the pointer would fire their bindings again.
+            fn pointer_event(&self) -> bool {
+                matches!(
+                    self.event,
+                    Some($crate::display::DisplayServerEvent::PointerMotion(_) | $crate::display::DisplayServerEvent::PointerRest(_))
+                )
+            }
+
+            fn keycodes_of(&self, key: Key) -> &[$crate::display::Keycode] {
+                match key {
+                    $( Key::$name => &self.$name, )*
+                    $( Key::$mode_name => &self.$mode_name, )*
+                }
+            }
+        }
+
+        impl $crate::state_machine::ManageKeyState for KeyState {
+
+            fn new(client: &mut dyn $crate::display::DisplayServerClient) -> Self {
+                let key_state = Self {
+                    keycodes: std::collections::HashSet::with_capacity($crate::state_machine::SIZE_OF_THE_ACTIVE_KEYS),
+                    modifiers: $crate::display::Modifiers::default(),
+                    $( $name: $crate::state_machine::grab_keysym(
+                        client,
+                        $key,
+                        $crate::define_keys!(@mask $($($modifier)|+)?),
+                    ),)*
+                    $( $mode_name: client.keycodes($mode_key),)*
+                    buttons: std::collections::HashSet::new(),
+                    pointer: $crate::display::Pointer::default(),
+                    pointer_resting: false,
+                    gesture: $crate::state_machine::Gesture::new(
+                        $crate::define_keys!(@gesture_distance $($gesture_distance)?),
+                    ),
+                    finished_gesture: None,
+                    history: $crate::state_machine::KeyHistory::new(
+                        $crate::define_keys!(@window TAP_WINDOW $($tap_window)?),
+                        $crate::define_keys!(@window CHORD_WINDOW $($chord_window)?),
+                    ),
+                    event: None,
+                };
+                $($( $crate::state_machine::grab_button(
+                    client,
+                    $button,
+                    $crate::define_keys!(@mask $($($button_modifier)|+)?),
+                ); )*)?
+                key_state
+            }
+            fn update(&mut self, e: $crate::display::DisplayServerEvent) {
+                self.finished_gesture = None;
+                self.event = Some(e);
+                self.history.update(e);
                 match e {
-                    crate::display::DisplayServerEvent::KeyRelease(k) => {
-                        println!("relesae: {:?}", k);
+                    $crate::display::DisplayServerEvent::KeyRelease(k, modifiers) => {
+                        self.modifiers = modifiers;
                         self.keycodes.remove(&k);},
-                    crate::display::DisplayServerEvent::KeyPress(k) => {self.keycodes.insert(k);},
+                    $crate::display::DisplayServerEvent::KeyPress(k, modifiers)
+                    | $crate::display::DisplayServerEvent::KeyRepeat(k, modifiers) => {
+                        self.modifiers = modifiers;
+                        self.keycodes.insert(k);},
+                    $crate::display::DisplayServerEvent::ButtonPress(button, modifiers) => {
+                        self.modifiers = modifiers;
+                        // another button cancels the gesture
+                        if self.buttons.is_empty() {
+                            self.gesture.begin();
+                        } else {
+                            self.gesture.cancel();
+                        }
+                        self.buttons.insert(button);},
+                    $crate::display::DisplayServerEvent::ButtonRelease(button, modifiers) => {
+                        self.modifiers = modifiers;
+                        self.buttons.remove(&button);
+                        if self.buttons.is_empty() {
+                            self.finished_gesture = self.gesture.end();
+                        }},
+                    $crate::display::DisplayServerEvent::PointerMotion(pointer) => {
+                        self.pointer = pointer;
+                        self.pointer_resting = false;
+                        self.gesture.add(pointer.x, pointer.y);},
+                    $crate::display::DisplayServerEvent::PointerRest(pointer) => {
+                        self.pointer = pointer;
+                        self.pointer_resting = true;},
                     _ => {
                         // do nothing
                     }
                 }
             }
+            fn regrab(&mut self, client: &mut dyn $crate::display::DisplayServerClient) {
+                $( $crate::state_machine::ungrab_keycodes(
+                    client,
+                    &self.$name,
+                    $crate::define_keys!(@mask $($($modifier)|+)?),
+                ); )*
+                self.keycodes.clear();
+                self.history.clear();
+                $( self.$name = $crate::state_machine::grab_keysym(
+                    client,
+                    $key,
+                    $crate::define_keys!(@mask $($($modifier)|+)?),
+                ); )*
+                $( self.$mode_name = client.keycodes($mode_key); )*
+                $($(
+                    let modifiers = $crate::define_keys!(@mask $($($button_modifier)|+)?);
+                    client.ungrab_button($button, modifiers);
+                    $crate::state_machine::grab_button(client, $button, modifiers);
+                )*)?
+            }
+            fn resync(&mut self, pressed: &[$crate::display::Keycode]) {
+                self.keycodes = pressed.iter().copied().collect();
+                self.history.resync(pressed);
+            }
         }
     };
+    (@gesture_distance) => {
+        $crate::state_machine::DEFAULT_GESTURE_DISTANCE
+    };
+    (@gesture_distance $gesture_distance:expr) => {
+        $gesture_distance
+    };
+    (@window $default:ident) => {
+        $crate::state_machine::$default
+    };
+    (@window $default:ident $ms:expr) => {
+        std::time::Duration::from_millis($ms)
+    };
+    (@mask $($modifier:ident)|*) => {
+        $crate::display::Modifier::grab_mask(&[$($crate::display::Modifier::$modifier),*])
+    };
+    ($($name:ident $([$($modifier:ident)|+])? => $key:expr),* $(,)?) => {
+        $crate::define_keys! { start: { $($name $([$($modifier)|+])? => $key),* } mode: {} }
+    };
 }
-pub(crate) use define_keys;
+pub use crate::define_keys;
 
 use crate::display;
 
 pub fn grab_keysym(
     client: &mut dyn display::DisplayServerClient,
     keysym: display::Keysym,
+    modifiers: u16,
 ) -> Vec<display::Keycode> {
-    let results = client.grab_keysym_checked(keysym, display::Modifier::Any as u16);
+    let results = client.grab_keysym_checked(keysym, modifiers);
     results.1.iter().for_each(|err| {
         println!("{:?}", err);
     });
     results.0
 }
+
+pub fn grab_button(client: &mut dyn display::DisplayServerClient, button: display::Button, modifiers: u16) {
+    if let Err(err) = client.grab_button_checked(button, modifiers) {
+        println!("{:?}", err);
+    }
+}
+
+pub fn ungrab_keycodes(
+    client: &mut dyn display::DisplayServerClient,
+    keycodes: &[display::Keycode],
+    modifiers: u16,
+) {
+    for keycode in keycodes {
+        client.ungrab_keycode(*keycode, modifiers);
+    }
+}
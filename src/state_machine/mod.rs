@@ -1,10 +1,14 @@
 mod executor;
 mod flow_controls;
+mod gesture;
+mod key_history;
 mod key_state;
 mod transition;
 
 #[allow(unused_imports)]
 pub use flow_controls::*;
 pub use executor::*;
+pub use gesture::*;
+pub use key_history::*;
 pub use key_state::*;
 pub use transition::*;
\ No newline at end of file
@@ -1,5 +1,72 @@
+use std::time::Duration;
+
+/// The defaults of `repeat!`, like the X autorepeat but independent of it.
+pub const REPEAT_DELAY: Duration = Duration::from_millis(300);
+pub const REPEAT_INTERVAL: Duration = Duration::from_millis(80);
+
+/// The node of a closure, see `NodeOut::Closure`.
+pub type ClosureNode<KeyState, UserState> =
+    Box<dyn FnMut(&KeyState, &mut UserState) -> NodeOut<KeyState, UserState>>;
+
 pub enum NodeOut<KeyState, UserState> {
     Next(fn(key_state: &KeyState, user_state: &mut UserState) -> Self),
-    // idea: NextMouse(fn(pos: Pos) -> Self),
+    /// A node that can capture data, like the parameters of a mode.
+    /// Use `Next` when nothing is captured, it needs no allocation.
+    Closure(ClosureNode<KeyState, UserState>),
+    /// Goes to `node`, and to `then` if there is no transition for the given time.
+    Timeout {
+        node: Box<Self>,
+        after: Duration,
+        then: Box<Self>,
+    },
+    /// Goes to `node`, and calls `action` after `delay`, then in every `interval`,
+    /// while the pressed key is held down.
+    Repeat {
+        node: Box<Self>,
+        delay: Duration,
+        interval: Duration,
+        action: Box<dyn FnMut()>,
+    },
+    /// Hides the key press and stays in the node, like for the first key of a chord,
+    /// or a tapped key pressed alone.
+    /// If the node takes one of the next events by a transition, the press is taken with it,
+    /// otherwise it is sent to the focused window after the given time, on its release,
+    /// or before the next key event that the node doesn't take.
+    /// In a mode the keyboard is grabbed, so it's the same as `None`.
+    HoldBack(Duration),
+    /// Goes to `node`, and replays the event to the focused window instead of hiding it.
+    /// Without a grab of the whole keyboard, the release goes to the focused window too.
+    Replay(Box<Self>),
     None,
 }
+
+impl<KeyState, UserState> NodeOut<KeyState, UserState> {
+    pub fn closure(node: impl FnMut(&KeyState, &mut UserState) -> Self + 'static) -> Self {
+        NodeOut::Closure(Box::new(node))
+    }
+
+    /// Leaves the node for `then`, if there is no transition for the given time.
+    pub fn with_timeout(self, after: Duration, then: Self) -> Self {
+        NodeOut::Timeout {
+            node: Box::new(self),
+            after,
+            then: Box::new(then),
+        }
+    }
+
+    /// The event of the transition is replayed, not hidden.
+    pub fn with_replay(self) -> Self {
+        NodeOut::Replay(Box::new(self))
+    }
+
+    /// Repeats the action while the key of the event is held down, independently of the X autorepeat.
+    /// The action is not called at the transition, only after `delay`.
+    pub fn with_repeat(self, delay: Duration, interval: Duration, action: impl FnMut() + 'static) -> Self {
+        NodeOut::Repeat {
+            node: Box::new(self),
+            delay,
+            interval,
+            action: Box::new(action),
+        }
+    }
+}
@@ -1,5 +1,9 @@
 use std::process::{Command};
 
+/// Runs the command and waits for its output.
+///
+/// The keyboard is frozen until the node returns, so use it only for quick queries,
+/// start programs with `run!` or `crate::process::spawn_shell`.
 #[allow(unused)]
 pub fn run_in_shell(command: &str) -> Option<String> {
     Command::new("sh")
@@ -1,6 +1,6 @@
 
 /// A way to simplify sequences like this:
-/// ```
+/// ```ignore
 /// if key.super_l() && key.c() {
 ///     next!(|key, _state| {
 ///         if key.esc() { restart!() }
@@ -37,7 +37,7 @@
 /// }
 /// ```
 /// to this:
-/// ```
+/// ```ignore
 /// sequence! {
 ///     key,
 ///     (super_l && c) => {
@@ -60,46 +60,198 @@
 ///     };
 /// }
 /// ```
+/// Besides the key names, a condition can have `(tap super_l)` for a key pressed alone and shortly,
+/// `(held esc 500)` for a key held down at least for the given milliseconds,
+/// `(2 * shift_l)` for a key tapped twice in a row,
+/// `(super_l then c)` for keys pressed in this order and still held,
+/// and `(chord s t)` for keys pressed at the same time in any order, the first one is held back meanwhile,
+/// see `KeyState::tapped`, `KeyState::held_for`, `KeyState::taps`, `KeyState::ordered` and `KeyState::chord`.
+///
+/// Like in sxhkd, `@` makes a binding fire on release: `(super_l && @c)` on the release of `c` while super is held,
+/// `(@ super_l && c)` when the whole combo is released, so the keys don't mix into a `xdotool type`.
+/// The press of a tapped key, and of the first key of such a combo, is held back while it's pressed alone,
+/// another key sends it to the focused window by XTest, see `NodeOut::HoldBack`.
+///
+/// The autorepeat of a held key doesn't trigger the bindings again, see `KeyState::repeated`.
+/// `repeat` instead of `:` runs the command again while the key is held, see `repeat!`:
+/// ```ignore
+/// sequence! {
+///     key,
+///     (@ super_l && t): "xdotool type hello";
+///     (super_l && equal) repeat "pactl set-sink-volume @DEFAULT_SINK@ +5%";
+///     (super_l && minus) repeat ("pactl set-sink-volume @DEFAULT_SINK@ -5%", 500, 100);
+///     (tap super_l): "rofi -show drun";
+///     (3 * esc): "systemctl --user restart rhkd";
+///     (chord s t): "notify-send chord";
+///     (super_l && (held q 1000)): "bspc quit";
+/// }
+/// ```
+#[macro_export]
 macro_rules! sequence {
     (@parse_body_first $key:ident $($condition:tt $symbol:tt $body:tt);* $(;)?) => {{
-        $(crate::tools::sequence!{
+        $($crate::sequence!{
             @parse_body_part $key $condition $symbol $body
+        }
+        $crate::sequence!{
+            @hide_press $key $condition
         })*
     }};
     (@parse_body $key:ident $($condition:tt $symbol:tt $body:tt);* $(;)?) => {{
-        if $key.esc() { restart!() }
-        $(crate::tools::sequence!{
+        if $key.esc() { $crate::restart!() }
+        $($crate::sequence!{
             @parse_body_part $key $condition $symbol $body
         })*
-        wait!()
+        $crate::wait!()
     }};
-    (@parse_condition $key:ident (($token:ident))) => {
-        sequence!(@parse_condition $key ($token))
+    (@parse_term $key:ident (tap $token:ident)) => {
+        $key.tapped(Key::$token)
+    };
+    (@parse_term $key:ident (held $token:ident $ms:literal)) => {
+        $key.held_for(Key::$token, ::std::time::Duration::from_millis($ms))
+    };
+    (@parse_term $key:ident (chord $($token:ident)+)) => {
+        $key.chord(&[$(Key::$token),+])
+    };
+    (@parse_term $key:ident ($first:ident $(then $token:ident)+)) => {
+        $key.ordered(&[Key::$first, $(Key::$token),+])
+    };
+    (@parse_term $key:ident ($count:literal * $token:ident)) => {
+        ($key.taps(Key::$token) == $count)
     };
-    (@parse_condition $key:ident ($token:ident)) => {
+    (@parse_term $key:ident ($token:tt)) => {
+        $crate::sequence!(@parse_term $key $token)
+    };
+    (@parse_term $key:ident $token:ident) => {
         $key.$token()
     };
+    // `@` marks a release: of a key in the combo, or of the whole combo when it's the first
+    (@parse_condition $key:ident (@ $($token:ident)&&+)) => {
+        $key.all_released(&[$(Key::$token),+])
+    };
+    (@parse_condition $key:ident ($($term:tt)+)) => {
+        $crate::sequence!(@parse_and $key release $($term)+)
+    };
     (@parse_condition $key:ident $token:ident) => {
         $key.$token()
     };
-    (@parse_condition $key:ident ($a:tt && $($b:tt)*)) => {
-        ($key.$a() && crate::tools::sequence!(@parse_condition $key ( $($b)*) ))
+    // the press of the keys that a release binding waits for
+    (@parse_press $key:ident ($($term:tt)+)) => {
+        $crate::sequence!(@parse_and $key press $($term)+)
+    };
+    (@parse_and $key:ident $mode:ident @ $token:ident && $($rest:tt)+) => {
+        ($crate::sequence!(@parse_at $key $mode $token) && $crate::sequence!(@parse_and $key $mode $($rest)+))
+    };
+    (@parse_and $key:ident $mode:ident @ $token:ident) => {
+        $crate::sequence!(@parse_at $key $mode $token)
+    };
+    (@parse_and $key:ident $mode:ident $term:tt && $($rest:tt)+) => {
+        ($crate::sequence!(@parse_term $key $term) && $crate::sequence!(@parse_and $key $mode $($rest)+))
+    };
+    (@parse_and $key:ident $mode:ident $term:tt) => {
+        $crate::sequence!(@parse_term $key $term)
+    };
+    (@parse_and $key:ident $mode:ident $($term:tt)+) => {
+        $crate::sequence!(@parse_term $key ($($term)+))
+    };
+    (@parse_at $key:ident release $token:ident) => {
+        $key.released(Key::$token)
+    };
+    (@parse_at $key:ident press $token:ident) => {
+        $key.$token()
+    };
+    // In the start node the press of a release binding is hidden too,
+    // otherwise the focused window would get the press without the release.
+    // The press of a tapped key, like in `(tap super_l)` or `(2 * esc)`, is held back when it's pressed alone,
+    // so its release comes here, not to the focused window. Another key sends it by XTest,
+    // so the other programs still get it as a modifier.
+    // In the modes the keyboard is grabbed, the other windows get nothing.
+    (@hide_press $key:ident (@ $first:ident $(&& $rest:ident)*)) => {
+        if $key.pressed_alone(Key::$first) {
+            return $crate::state_machine::NodeOut::HoldBack($crate::state_machine::TAP_DURATION);
+        }
+        $crate::sequence!(@hide_combo $key [$first] $($rest)&&*);
+    };
+    (@hide_press $key:ident ($($term:tt)+)) => {
+        $crate::sequence!(@find_at $key ($($term)+) $($term)+);
+        $crate::sequence!(@find_tap $key $($term)+);
+        $crate::sequence!(@find_chord $key $($term)+);
+    };
+    (@hide_press $key:ident $token:ident) => {};
+    (@find_at $key:ident $condition:tt) => {};
+    (@find_at $key:ident $condition:tt @ $($rest:tt)*) => {
+        if $crate::sequence!(@parse_press $key $condition) {
+            $crate::restart!();
+        }
+    };
+    (@find_at $key:ident $condition:tt $first:tt $($rest:tt)*) => {
+        $crate::sequence!(@find_at $key $condition $($rest)*)
+    };
+    // the next keys of a combo released together, each when the keys before it are held,
+    // otherwise the first release would go to the focused window,
+    // the held back first key is taken with them
+    (@hide_combo $key:ident [$($held:ident)*] $token:ident $(&& $rest:ident)*) => {
+        if $key.pressed(Key::$token) $(&& $key.$held())* {
+            $crate::restart!();
+        }
+        $crate::sequence!(@hide_combo $key [$($held)* $token] $($rest)&&*);
+    };
+    (@hide_combo $key:ident [$($held:ident)*]) => {};
+    // the first key of a chord is held back, it's typed only if the chord is not finished in time
+    (@find_chord $key:ident) => {};
+    (@find_chord $key:ident chord $($token:ident)+) => {
+        if ($($key.pressed(Key::$token))||+) && !$key.chord(&[$(Key::$token),+]) {
+            return $crate::state_machine::NodeOut::HoldBack($key.chord_window());
+        }
+    };
+    (@find_chord $key:ident ($($inner:tt)*) $($rest:tt)*) => {
+        $crate::sequence!(@find_chord $key $($inner)*);
+        $crate::sequence!(@find_chord $key $($rest)*);
+    };
+    (@find_chord $key:ident $first:tt $($rest:tt)*) => {
+        $crate::sequence!(@find_chord $key $($rest)*);
+    };
+    (@find_tap $key:ident) => {};
+    (@find_tap $key:ident tap $token:ident $($rest:tt)*) => {
+        if $key.pressed_alone(Key::$token) {
+            return $crate::state_machine::NodeOut::HoldBack($crate::state_machine::TAP_DURATION);
+        }
+        $crate::sequence!(@find_tap $key $($rest)*);
+    };
+    (@find_tap $key:ident $count:literal * $token:ident $($rest:tt)*) => {
+        $crate::sequence!(@find_tap $key tap $token $($rest)*);
+    };
+    (@find_tap $key:ident ($($inner:tt)*) $($rest:tt)*) => {
+        $crate::sequence!(@find_tap $key $($inner)*);
+        $crate::sequence!(@find_tap $key $($rest)*);
+    };
+    (@find_tap $key:ident $first:tt $($rest:tt)*) => {
+        $crate::sequence!(@find_tap $key $($rest)*);
     };
     (@parse_body_part $key:ident $condition:tt => { $($rest:tt)* }) => {
-        if crate::tools::sequence!(@parse_condition $key $condition) {
-            crate::state_machine::next!(|key, _state| {
-                crate::tools::sequence!(@parse_body key $($rest)*)
+        if !$key.repeated() && $crate::sequence!(@parse_condition $key $condition) {
+            $crate::next!(|key, _state| {
+                $crate::sequence!(@parse_body key $($rest)*)
             });
         }
     };
     (@parse_body_part $key:ident $condition:tt : $body:literal) => {
-        if crate::tools::sequence!(@parse_condition $key $condition) {
-            crate::state_machine::run!($body);
+        if !$key.repeated() && $crate::sequence!(@parse_condition $key $condition) {
+            $crate::run!($body);
+        }
+    };
+    (@parse_body_part $key:ident $condition:tt repeat $body:literal) => {
+        if !$key.repeated() && $crate::sequence!(@parse_condition $key $condition) {
+            $crate::repeat!($body);
+        }
+    };
+    (@parse_body_part $key:ident $condition:tt repeat ($body:literal, $delay:literal, $interval:literal)) => {
+        if !$key.repeated() && $crate::sequence!(@parse_condition $key $condition) {
+            $crate::repeat!($body, $delay, $interval);
         }
     };
     ($key:ident, $($rest:tt)*) => {
-        crate::tools::sequence!(@parse_body_first $key $($rest)*)
+        $crate::sequence!(@parse_body_first $key $($rest)*)
     }
 }
 
-pub(crate) use sequence;
\ No newline at end of file
+pub use crate::sequence;
\ No newline at end of file
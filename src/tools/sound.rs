@@ -19,14 +19,14 @@ pub fn get_sink_name(sink_id: usize) -> Option<String> {
 #[allow(unused)]
 pub fn get_sink_inputs() -> Vec<usize> {
     run_in_shell("pactl list sink-inputs short")
-        .and_then(|out| get_ids_from_list(out))
+        .and_then(get_ids_from_list)
         .unwrap_or(vec![])
 }
 
 #[allow(unused)]
 pub fn get_sinks() -> Vec<usize> {
     run_in_shell("pactl list sinks short")
-        .and_then(|out| get_ids_from_list(out))
+        .and_then(get_ids_from_list)
         .unwrap_or(vec![])
 }
 
@@ -81,8 +81,14 @@ pub fn get_sink_id_by_name(name: &str) -> Option<usize> {
 }
 
 /// switches to the next audio device
+///
+/// It runs in the background, so the keyboard is not frozen while pactl is working.
 #[allow(unused)]
 pub fn cycle_sinks(left: bool) {
+    std::thread::spawn(move || cycle_sinks_blocking(left));
+}
+
+fn cycle_sinks_blocking(left: bool) {
     if let Some(default_sink_name) = get_default_sink_name() {
         let default_sink_id = get_sink_id_by_name(&default_sink_name).unwrap();
         let mut sinks = get_sinks();
@@ -1,9 +1,10 @@
 use std::{env, fmt::Display};
 
-use super::run_in_shell;
+use crate::process::spawn_shell;
 
 #[allow(unused)]
-struct Urxvt {
+#[derive(Clone)]
+pub struct Urxvt {
     shell: String,
     path: Option<String>,
     bg: Option<Color>,
@@ -30,7 +31,7 @@ impl Urxvt {
     }
 
     pub fn run(self) {
-        run_in_shell(&format!(
+        spawn_shell(&format!(
             "urxvt {background} -e sh -c '{path} {shell}'",
             background = if let Some(color) = self.bg {
                 format!(r##"--background-expr 'keep {{ solid "{}" }}' "##, color)
@@ -47,8 +48,15 @@ impl Urxvt {
     }
 }
 
+impl Default for Urxvt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[allow(unused)]
-struct Color((u8, u8, u8));
+#[derive(Clone)]
+pub struct Color(pub (u8, u8, u8));
 
 impl Display for Color {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {